@@ -0,0 +1,2 @@
+ALTER TABLE lb_players
+    ADD COLUMN volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06;
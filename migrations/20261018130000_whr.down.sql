@@ -0,0 +1 @@
+DROP TABLE lb_player_days_whr;
@@ -0,0 +1,7 @@
+CREATE TABLE lb_player_days_whr (
+    lb_player_id BIGINT REFERENCES lb_players ON DELETE CASCADE,
+    day DATE,
+    rating DOUBLE PRECISION NOT NULL,
+    rating_deviation DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (lb_player_id, day)
+);
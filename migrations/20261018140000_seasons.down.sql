@@ -0,0 +1 @@
+DROP TABLE lb_season_standings;
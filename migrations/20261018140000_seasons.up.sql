@@ -0,0 +1,11 @@
+CREATE TABLE lb_season_standings (
+    lb_id BIGINT,
+    season_num INTEGER,
+    placement INTEGER,
+    lb_player_id BIGINT REFERENCES lb_players ON DELETE SET NULL,
+    name TEXT NOT NULL,
+    rating DOUBLE PRECISION NOT NULL,
+    rating_deviation DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (lb_id, season_num, placement),
+    FOREIGN KEY (lb_id, season_num) REFERENCES lb_seasons ON DELETE CASCADE
+);
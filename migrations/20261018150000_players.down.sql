@@ -0,0 +1,5 @@
+ALTER TABLE lb_players
+    DROP COLUMN player_id;
+
+DROP TABLE player_names;
+DROP TABLE players;
@@ -0,0 +1,19 @@
+CREATE TABLE players (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE player_names (
+    name TEXT PRIMARY KEY,
+    player_id BIGINT NOT NULL REFERENCES players ON DELETE CASCADE
+);
+
+INSERT INTO players (name) SELECT DISTINCT name FROM lb_players;
+INSERT INTO player_names (name, player_id) SELECT name, id FROM players;
+
+ALTER TABLE lb_players
+    ADD COLUMN player_id BIGINT REFERENCES players ON DELETE CASCADE;
+UPDATE lb_players l SET player_id = p.id FROM players p WHERE p.name = l.name;
+ALTER TABLE lb_players
+    ALTER COLUMN player_id SET NOT NULL,
+    ADD UNIQUE (lb_id, player_id);
@@ -0,0 +1,4 @@
+DROP TABLE link_codes;
+
+ALTER TABLE players
+    DROP COLUMN discord_id;
@@ -0,0 +1,8 @@
+ALTER TABLE players
+    ADD COLUMN discord_id BIGINT UNIQUE;
+
+CREATE TABLE link_codes (
+    code TEXT PRIMARY KEY,
+    discord_id BIGINT NOT NULL UNIQUE,
+    expires TIMESTAMPTZ NOT NULL
+);
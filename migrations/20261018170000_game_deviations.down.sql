@@ -0,0 +1,2 @@
+ALTER TABLE lb_game_teams
+    DROP COLUMN new_deviation;
@@ -0,0 +1,2 @@
+ALTER TABLE lb_game_teams
+    ADD COLUMN new_deviation DOUBLE PRECISION[];
@@ -0,0 +1,3 @@
+ALTER TABLE lb_players
+    DROP COLUMN placement,
+    DROP COLUMN last_placement;
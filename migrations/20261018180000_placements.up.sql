@@ -0,0 +1,3 @@
+ALTER TABLE lb_players
+    ADD COLUMN placement INTEGER,
+    ADD COLUMN last_placement INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE lb_players
+    DROP COLUMN last_played;
@@ -0,0 +1,7 @@
+ALTER TABLE lb_players
+    ADD COLUMN last_played DATE;
+
+UPDATE lb_players l SET last_played = (
+    SELECT MAX(g.day) FROM lb_games g JOIN lb_game_teams t ON t.game_id = g.id
+    WHERE g.lb_id = l.lb_id AND l.id = ANY(t.player_ids)
+);
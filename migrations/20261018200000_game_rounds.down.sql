@@ -0,0 +1 @@
+DROP TABLE lb_game_rounds;
@@ -0,0 +1,7 @@
+CREATE TABLE lb_game_rounds (
+    game_id BIGINT REFERENCES lb_games ON DELETE CASCADE,
+    round INTEGER,
+    winner INTEGER,
+    duration DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (game_id, round)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE lb_game_teams
+    DROP COLUMN side;
+ALTER TABLE lb_games
+    DROP COLUMN map_id,
+    DROP COLUMN struck_maps;
+
+DROP TABLE maps;
@@ -0,0 +1,11 @@
+CREATE TABLE maps (
+    id BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    author TEXT NOT NULL
+);
+
+ALTER TABLE lb_games
+    ADD COLUMN map_id BIGINT REFERENCES maps,
+    ADD COLUMN struck_maps BIGINT[] NOT NULL DEFAULT '{}';
+ALTER TABLE lb_game_teams
+    ADD COLUMN side INTEGER;
@@ -0,0 +1,5 @@
+DROP TABLE map_pool_maps;
+DROP TABLE map_pools;
+
+ALTER TABLE maps
+    DROP COLUMN data;
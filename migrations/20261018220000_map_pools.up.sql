@@ -0,0 +1,13 @@
+ALTER TABLE maps
+    ADD COLUMN data TEXT;
+
+CREATE TABLE map_pools (
+    name TEXT PRIMARY KEY
+);
+
+CREATE TABLE map_pool_maps (
+    pool TEXT REFERENCES map_pools ON DELETE CASCADE,
+    map_id BIGINT REFERENCES maps ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (pool, map_id)
+);
@@ -0,0 +1,17 @@
+CREATE TABLE tournaments (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    lb_id BIGINT NOT NULL REFERENCES leaderboard ON DELETE CASCADE,
+    format JSONB NOT NULL,
+    rounds INTEGER,
+    channel BIGINT NOT NULL,
+    room TEXT NOT NULL,
+    bracket JSONB
+);
+
+CREATE TABLE tournament_players (
+    tournament_id BIGINT NOT NULL REFERENCES tournaments ON DELETE CASCADE,
+    player_id BIGINT NOT NULL REFERENCES players ON DELETE CASCADE,
+    seed INTEGER,
+    PRIMARY KEY (tournament_id, player_id)
+);
@@ -0,0 +1,2 @@
+DROP TABLE challenges;
+ALTER TABLE leaderboard DROP COLUMN room;
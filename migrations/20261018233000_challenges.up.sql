@@ -0,0 +1,11 @@
+ALTER TABLE leaderboard ADD COLUMN room TEXT;
+
+CREATE TABLE challenges (
+    id BIGSERIAL PRIMARY KEY,
+    lb_id BIGINT NOT NULL REFERENCES leaderboard ON DELETE CASCADE,
+    challenger BIGINT NOT NULL,
+    opponent BIGINT NOT NULL,
+    time TIMESTAMPTZ,
+    status TEXT NOT NULL DEFAULT 'pending',
+    thread BIGINT
+);
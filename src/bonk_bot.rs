@@ -56,54 +56,74 @@ impl BonkBotValue {
         }
     }
 
-    pub async fn open_room(
+    ///Gets a sender for a leaderboard, starting the leaderboard if it isn't running.
+    pub async fn leaderboard_tx(
         &self,
         ctx: &serenity::all::Context,
-        room_parameters: room_maker::RoomParameters,
-    ) -> Result<String> {
-        let mut leaderboard_tx: Option<mpsc::Sender<LeaderboardMessage>> = None;
+        abbreviation: &str,
+    ) -> Result<mpsc::Sender<LeaderboardMessage>> {
+        let db = {
+            let data = ctx.data.read().await;
+            data.get::<crate::DatabaseKey>()
+                .cloned()
+                .ok_or(anyhow!("Failed to connect to database."))?
+                .db
+        };
 
-        let data = ctx.data.read().await;
-        let db = data
-            .get::<crate::DatabaseKey>()
-            .cloned()
-            .ok_or(anyhow!("Failed to connect to database."))?
-            .db;
+        let rows: Vec<(i64, serde_json::Value)> =
+            sqlx::query_as("SELECT id, settings FROM leaderboard WHERE abbreviation = $1")
+                .bind(abbreviation)
+                .fetch_all(db.as_ref())
+                .await?;
 
-        if let Some(lb) = &room_parameters.leaderboard {
-            let rows: Vec<(i64, serde_json::Value)> =
-                sqlx::query_as("SELECT id, settings FROM leaderboard WHERE abbreviation = $1")
-                    .bind(lb)
-                    .fetch_all(db.as_ref())
-                    .await?;
-
-            if rows.len() < 1 {
-                return Err(anyhow!("Leaderboard not found."));
-            }
+        if rows.len() < 1 {
+            return Err(anyhow!("Leaderboard not found."));
+        }
+
+        let id = rows.get(0).context("Error while loading leaderboard.")?.0;
+        let settings: LeaderboardSettings = serde_json::from_value(
+            rows.get(0)
+                .context("Error while loading leaderboard.")?
+                .1
+                .clone(),
+        )?;
+
+        let mut leaderboards_tx = self.leaderboards_tx.lock().await;
+        leaderboards_tx.retain(|x| x.1.strong_count() > 0);
+        let leaderboard_tx = leaderboards_tx
+            .iter()
+            .find(|x| x.0 == id)
+            .and_then(|(_, leaderboard_wtx)| leaderboard_wtx.upgrade());
+
+        if let Some(leaderboard_tx) = leaderboard_tx {
+            Ok(leaderboard_tx)
+        } else {
+            let (tx, rx) = mpsc::channel(10);
+            let mut leaderboard = Leaderboard::new(rx, ctx.clone(), settings).await?;
 
-            let id = rows.get(0).context("Error while loading leaderboard.")?.0;
-            let settings: LeaderboardSettings = serde_json::from_value(
-                rows.get(0)
-                    .context("Error while loading leaderboard.")?
-                    .1
-                    .clone(),
-            )?;
+            tokio::spawn(async move { leaderboard.run().await });
 
-            let mut leaderboards_tx = self.leaderboards_tx.lock().await;
-            leaderboards_tx.retain(|x| x.1.strong_count() > 0);
-            let leaderboard_wtx = leaderboards_tx.iter().find(|x| x.0 == id);
+            leaderboards_tx.push((id, tx.clone().downgrade()));
+            Ok(tx)
+        }
+    }
 
-            if let Some((_, leaderboard_wtx)) = leaderboard_wtx {
-                leaderboard_tx = leaderboard_wtx.clone().upgrade();
-            } else {
-                let (tx, rx) = mpsc::channel(10);
-                let mut leaderboard = Leaderboard::new(rx, ctx.clone(), settings).await?;
+    pub async fn open_room(
+        &self,
+        ctx: &serenity::all::Context,
+        mut room_parameters: room_maker::RoomParameters,
+    ) -> Result<String> {
+        let db = {
+            let data = ctx.data.read().await;
+            data.get::<crate::DatabaseKey>().cloned()
+        }
+        .context("Failed to connect to database.")?;
+        room_parameters.resolve_map_pool(&db.db).await?;
 
-                tokio::spawn(async move { leaderboard.run().await });
+        let mut leaderboard_tx: Option<mpsc::Sender<LeaderboardMessage>> = None;
 
-                leaderboards_tx.push((id, tx.clone().downgrade()));
-                leaderboard_tx = Some(tx);
-            }
+        if let Some(lb) = &room_parameters.leaderboard {
+            leaderboard_tx = Some(self.leaderboard_tx(ctx, lb).await?);
         }
 
         let (tx, rx) = oneshot::channel();
@@ -2,17 +2,29 @@ use std::time::Duration;
 
 use rand::{seq::IndexedRandom, RngExt};
 use serde_json::json;
+use serenity::all::{CreateMessage, UserId};
 use tokio::{
     sync::oneshot,
     time::{self, Instant},
 };
 
 use crate::{
-    bonk_bot::bonk_room::{GamePlayers, Player, State},
-    leaderboard::LeaderboardMessage,
+    bonk_bot::bonk_room::{draft_picker, GamePlayers, Player, State},
+    leaderboard::{
+        stats::{GameResult, PlayerGame, PlayerQuery},
+        LeaderboardMessage,
+    },
+    players,
 };
 
-use super::{bonk_room::BonkRoom, room_maker::Mode};
+use super::{
+    bonk_room::BonkRoom,
+    room_maker::{MapSelection, Mode},
+};
+
+const HISTORY_GAMES: usize = 5;
+///How long a captain has to undo a pick.
+const UNDO_TIME: Duration = Duration::from_secs(5);
 
 pub async fn discord(room: &mut BonkRoom) {
     let Ok(response) = dotenv::var("DISCORD_SERVER_LINK") else {
@@ -41,30 +53,48 @@ pub async fn leaderboard(room: &mut BonkRoom, name: String) {
     room.chat(response).await;
 }
 
-pub async fn elo(room: &mut BonkRoom, id: i32, name: String) {
-    let full_name;
-    if name != "" {
-        let keys = room
-            .queue
-            .iter()
-            .filter(|p| p.1.in_room)
-            .map(|p| p.1.name.clone())
-            .collect::<Vec<String>>();
+pub async fn link(room: &mut BonkRoom, id: i32, code: String) {
+    //Guests can pick any name so they can't link accounts.
+    if room.room_parameters.min_level < 1 {
+        room.chat("Accounts can only be linked in rooms that don't allow guests.".to_string())
+            .await;
+        return;
+    }
 
-        let matches = fuzzy_finder(&name, &keys);
-        if matches.len() != 1 {
-            room.chat("I couldn't find a match.".to_string()).await;
-            return;
-        }
-        let Some(player) = matches.get(0) else { return };
-        full_name = player.clone();
-    } else {
-        let Some(player) = room.queue.iter().find(|p| p.1.id == id) else {
-            return;
-        };
+    let Some(player) = room.queue.iter().find(|p| p.1.id == id) else {
+        return;
+    };
+    let name = player.1.name.clone();
+
+    let db = {
+        let data = room.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    };
+    let Some(db) = db else {
+        return;
+    };
+
+    match players::link(&db.db, code.trim(), &name).await {
+        Ok(discord_id) => {
+            room.chat(format!("{} is now linked to Discord.", name))
+                .await;
 
-        full_name = player.1.name.clone();
+            let _ = UserId::new(discord_id)
+                .direct_message(
+                    &room.http,
+                    CreateMessage::new()
+                        .content(format!("Your Discord account is now linked to {}.", name)),
+                )
+                .await;
+        }
+        Err(e) => room.chat(e.to_string()).await,
     }
+}
+
+pub async fn elo(room: &mut BonkRoom, id: i32, name: String) {
+    let Some(full_name) = room_player_name(room, id, name).await else {
+        return;
+    };
 
     let Some(leaderboard_tx) = &room.leaderboard_tx else {
         return;
@@ -83,6 +113,117 @@ pub async fn elo(room: &mut BonkRoom, id: i32, name: String) {
     room.chat(response).await;
 }
 
+pub async fn history(room: &mut BonkRoom, id: i32, name: String) {
+    let Some(full_name) = room_player_name(room, id, name).await else {
+        return;
+    };
+
+    let Some(leaderboard_tx) = &room.leaderboard_tx else {
+        return;
+    };
+
+    let (profile, profile_rx) = oneshot::channel();
+    let _ = leaderboard_tx
+        .send(LeaderboardMessage::Profile {
+            query: PlayerQuery::Name(full_name),
+            profile,
+        })
+        .await;
+    let Ok(Ok(profile)) = profile_rx.await else {
+        return;
+    };
+
+    let recent: Vec<&PlayerGame> = profile.games.iter().rev().take(HISTORY_GAMES).collect();
+    let (Some(newest), Some(oldest)) = (recent.first(), recent.last()) else {
+        room.chat(format!("{} hasn't played any games.", profile.player.name))
+            .await;
+        return;
+    };
+
+    room.chat(format!(
+        "{}'s last {}: {} ({:+.0}). {}W {}L {}D overall.",
+        profile.player.name,
+        recent.len(),
+        recent
+            .iter()
+            .rev()
+            .map(|game| game.result.letter())
+            .collect::<Vec<&str>>()
+            .join(" "),
+        newest.new_rating.round() - oldest.old_rating.round(),
+        profile.count(GameResult::Win),
+        profile.count(GameResult::Loss),
+        profile.count(GameResult::Draw),
+    ))
+    .await;
+}
+
+pub async fn h2h(room: &mut BonkRoom, id: i32, name: String) {
+    if name.is_empty() {
+        room.chat("Use !h2h <name> to compare yourself with another player.".to_string())
+            .await;
+        return;
+    }
+    let Some(own_name) = room_player_name(room, id, String::new()).await else {
+        return;
+    };
+    let Some(full_name) = room_player_name(room, id, name).await else {
+        return;
+    };
+
+    let Some(leaderboard_tx) = &room.leaderboard_tx else {
+        return;
+    };
+
+    let (h2h, h2h_rx) = oneshot::channel();
+    let _ = leaderboard_tx
+        .send(LeaderboardMessage::HeadToHead {
+            a: PlayerQuery::Name(own_name),
+            b: PlayerQuery::Name(full_name),
+            h2h,
+        })
+        .await;
+    let response = match h2h_rx.await {
+        Ok(Ok(h2h)) => format!(
+            "{} vs {}: {}W {}L {}D. {} has a {:.0}% chance to win.",
+            h2h.a.name,
+            h2h.b.name,
+            h2h.count(GameResult::Win),
+            h2h.count(GameResult::Loss),
+            h2h.count(GameResult::Draw),
+            h2h.a.name,
+            h2h.win_probability * 100.
+        ),
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => return,
+    };
+    room.chat(response).await;
+}
+
+///Finds the full name of a player in the room from part of their name or the name of the
+///player with the id if no name is given.
+async fn room_player_name(room: &mut BonkRoom, id: i32, name: String) -> Option<String> {
+    if name != "" {
+        let keys = room
+            .queue
+            .iter()
+            .filter(|p| p.1.in_room)
+            .map(|p| p.1.name.clone())
+            .collect::<Vec<String>>();
+
+        let matches = fuzzy_finder(&name, &keys);
+        if matches.len() != 1 {
+            room.chat("I couldn't find a match.".to_string()).await;
+            return None;
+        }
+        matches.get(0).cloned()
+    } else {
+        let player = room.queue.iter().find(|p| p.1.id == id)?;
+
+        Some(player.1.name.clone())
+    }
+}
+
 pub async fn pick(room: &mut BonkRoom, id: i32, name: String) {
     let State::Pick = room.state else {
         return;
@@ -190,23 +331,19 @@ pub async fn pick(room: &mut BonkRoom, id: i32, name: String) {
                 }
 
                 team.push(matched.1.clone());
-
-                match teams
-                    .iter()
-                    .map(|t| t.len() >= room.room_parameters.team_size)
-                    .reduce(|a, b| a && b)
-                {
-                    Some(true) => room.start_map_selection().await,
-                    Some(false) => loop {
-                        picker_idx = (picker_idx + 1) % room.room_parameters.team_num;
-
-                        let Some(team) = teams.get(picker_idx) else {
-                            return;
-                        };
-                        if team.len() >= room.room_parameters.team_size {
-                            continue;
-                        }
-                        let Some(picker) = team.get(0) else {
+                room.last_pick = Some((picker_idx, matched.1.id, Instant::now()));
+
+                let team_sizes = teams.iter().map(Vec::len).collect::<Vec<usize>>();
+                match draft_picker(
+                    &team_sizes,
+                    room.room_parameters.team_size,
+                    &room.room_parameters.draft_order,
+                ) {
+                    None => room.start_map_selection().await,
+                    Some(next_picker) => {
+                        picker_idx = next_picker;
+                        let Some(picker) = teams.get(picker_idx).and_then(|team| team.first())
+                        else {
                             return;
                         };
 
@@ -216,9 +353,7 @@ pub async fn pick(room: &mut BonkRoom, id: i32, name: String) {
                         room.warning_step = 0;
                         room.chat(format!("{}, pick a teammate.", picker.name))
                             .await;
-                        break;
-                    },
-                    _ => (),
+                    }
                 }
             }
 
@@ -228,6 +363,63 @@ pub async fn pick(room: &mut BonkRoom, id: i32, name: String) {
     }
 }
 
+///Takes back a captain's last pick if it was made within UNDO_TIME and the game hasn't
+///started.
+pub async fn undo(room: &mut BonkRoom, id: i32) {
+    let (State::Pick | State::MapSelection | State::Ready) = room.state else {
+        return;
+    };
+    let GamePlayers::Teams {
+        mut teams,
+        picker_idx: _,
+    } = room.game_players.clone()
+    else {
+        return;
+    };
+    let Some((team_idx, picked_id, picked_at)) = room.last_pick else {
+        return;
+    };
+    let Some(captain) = teams.get(team_idx).and_then(|team| team.first()).cloned() else {
+        return;
+    };
+    if captain.id != id {
+        return;
+    }
+    if picked_at.elapsed() > UNDO_TIME {
+        room.chat("It's too late to undo that pick.".to_string())
+            .await;
+        return;
+    }
+    let Some(idx) = teams[team_idx].iter().position(|p| p.id == picked_id) else {
+        return;
+    };
+
+    let picked = teams[team_idx].remove(idx);
+    let _ = room
+        .client
+        .execute(
+            "sgrAPI.toolFunctions.networkEngine.changeOtherTeam(arguments[0], arguments[1]);",
+            vec![json!(picked.id), json!(0)],
+        )
+        .await;
+
+    room.last_pick = None;
+    room.game_players = GamePlayers::Teams {
+        teams,
+        picker_idx: team_idx,
+    };
+    room.transition_timer = Box::pin(time::sleep(Duration::from_secs(
+        room.room_parameters.pick_time,
+    )));
+    room.warning_step = 0;
+    room.state = State::Pick;
+    room.chat(format!(
+        "{} was put back in the queue. {}, pick a teammate.",
+        picked.name, captain.name
+    ))
+    .await;
+}
+
 pub async fn any(room: &mut BonkRoom, id: i32) {
     let State::Pick = room.state else {
         return;
@@ -246,8 +438,13 @@ pub async fn any(room: &mut BonkRoom, id: i32) {
         pick(room, id, selection).await;
     }
 }
-pub async fn strike(room: &mut BonkRoom, id: i32) {
+pub async fn strike(room: &mut BonkRoom, id: i32, map: String) {
     if let State::MapSelection = room.state {
+        if !room.map_pickers.is_empty() {
+            choose_map(room, id, map).await;
+            return;
+        }
+
         let start = room
             .transition_timer
             .deadline()
@@ -318,6 +515,10 @@ pub async fn strike(room: &mut BonkRoom, id: i32) {
             if let Some(map_strike) = room.map_strikes.get_mut(new_map.0) {
                 *map_strike = true;
             }
+            if let Some(current_map) = room.current_map {
+                room.struck_maps.push(current_map);
+            }
+            room.current_map = Some(new_map.0);
             let _ = room
                 .client
                 .execute(
@@ -380,6 +581,73 @@ pub async fn strike(room: &mut BonkRoom, id: i32) {
     }
 }
 
+///Bans or picks a map by its number in the map list.
+async fn choose_map(room: &mut BonkRoom, id: i32, map: String) {
+    let Some(side) = room.map_pickers.get(room.map_turn % room.map_pickers.len()) else {
+        return;
+    };
+    if !side.contains(&id) {
+        return;
+    }
+    let Some(player) = room.queue.iter().find(|p| p.1.id == id) else {
+        return;
+    };
+    let player_name = player.1.name.clone();
+
+    let choice = map.trim().parse::<usize>().ok().and_then(|number| {
+        room.remaining_maps()
+            .into_iter()
+            .find(|(i, _)| *i + 1 == number)
+    });
+    let Some((map_idx, map_name)) = choice else {
+        room.chat("Use !s with a number from the map list.".to_string())
+            .await;
+        return;
+    };
+
+    let message;
+    if room.room_parameters.map_selection == MapSelection::Ban {
+        if let Some(map_strike) = room.map_strikes.get_mut(map_idx) {
+            *map_strike = true;
+        }
+        room.struck_maps.push(map_idx);
+        room.map_turn += 1;
+
+        let remaining_maps = room.remaining_maps();
+        if remaining_maps.len() > 1 {
+            room.transition_timer = Box::pin(time::sleep(Duration::from_secs(
+                room.room_parameters.strike_time,
+            )));
+            room.warning_step = 0;
+            room.chat(format!("{} banned {}.", player_name, map_name))
+                .await;
+            room.announce_map_turn().await;
+            return;
+        }
+
+        let Some((last_idx, last_name)) = remaining_maps.into_iter().next() else {
+            return;
+        };
+        room.load_map(last_idx).await;
+        message = format!(
+            "{} banned {}. {} is the last map left. Use !r to start.",
+            player_name, map_name, last_name
+        );
+    } else {
+        room.load_map(map_idx).await;
+        message = format!("{} picked {}. Use !r to start.", player_name, map_name);
+    }
+
+    room.map_pickers = vec![];
+    room.transition_timer = Box::pin(time::sleep(Duration::from_secs(
+        room.room_parameters.ready_time,
+    )));
+    room.warning_step = 0;
+    room.state = State::Ready;
+    room.chat(message).await;
+    room.check_ready(false).await;
+}
+
 pub async fn ready(room: &mut BonkRoom, id: i32) {
     if room.state == State::MapSelection || room.state == State::Ready {
         let player = room.queue.iter_mut().find(|p| p.1.id == id);
@@ -476,6 +744,7 @@ pub async fn reset(room: &mut BonkRoom, id: i32) {
         }
 
         room.vote_reset = vec![];
+        room.round_start = Instant::now();
     }
 }
 
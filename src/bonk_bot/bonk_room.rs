@@ -19,15 +19,18 @@ use tokio::select;
 use tokio::time::Interval;
 use tokio::time::Sleep;
 use tokio::{
-    sync::mpsc,
+    sync::{mpsc, oneshot},
     time::{self, Instant},
 };
 
 use crate::bonk_bot::events;
 use crate::bonk_bot::room_maker;
+use crate::bonk_bot::room_maker::DraftOrder;
+use crate::bonk_bot::room_maker::MapSelection;
 use crate::bonk_bot::room_maker::Mode;
 use crate::bonk_bot::room_maker::Queue;
 use crate::bonk_bot::BonkBotKey;
+use crate::leaderboard::GameMap;
 use crate::leaderboard::LeaderboardMessage;
 
 //use super::bonk_commands;
@@ -60,10 +63,29 @@ pub struct BonkRoom {
     pub game_players: GamePlayers,
     pub team_flip: bool,
     pub map_strikes: Vec<bool>,
+    ///Index of the loaded map in room_parameters.maps.
+    pub current_map: Option<usize>,
+    ///Maps that were struck before the current map.
+    pub struck_maps: Vec<usize>,
     //(id, strikes)
     pub player_strikes: Vec<(i32, u32)>,
+    ///Ids of the players on each side that bans or picks a map in turn. Empty when maps
+    ///are struck at random.
+    pub map_pickers: Vec<Vec<i32>>,
+    pub map_turn: usize,
+    ///Ids of the players on the losing side of the last game.
+    pub last_losers: Vec<i32>,
+    ///The team index and id of the last player drafted with !p or !any and when they were
+    ///picked. Captains who run out of pick time are kicked, so there are no other picks.
+    pub last_pick: Option<(usize, i32, Instant)>,
     pub vote_reset: Vec<i32>,
     pub vote_cancel: Vec<i32>,
+    ///Scores from the last poll. Indexed by player id, by team or by football team
+    ///depending on the mode.
+    pub round_scores: Vec<Option<i32>>,
+    pub round_start: Instant,
+    //(score index of the winner, duration)
+    pub rounds: Vec<(usize, Duration)>,
 }
 
 #[derive(Clone, Debug)]
@@ -153,6 +175,9 @@ impl BonkRoom {
             room_maker::Queue::FFA => GamePlayers::FFA { in_game: vec![] },
         };
 
+        //The first map is loaded when the room is made.
+        let current_map = (!room_parameters.maps.is_empty()).then_some(0);
+
         BonkRoom {
             link,
             http,
@@ -175,8 +200,17 @@ impl BonkRoom {
             team_flip: false,
             player_strikes: vec![],
             map_strikes: vec![],
+            map_pickers: vec![],
+            map_turn: 0,
+            last_losers: vec![],
+            last_pick: None,
+            current_map,
+            struck_maps: vec![],
             vote_reset: vec![],
             vote_cancel: vec![],
+            round_scores: vec![],
+            round_start: Instant::now(),
+            rounds: vec![],
         }
     }
 
@@ -295,6 +329,7 @@ impl BonkRoom {
     pub async fn reset(&mut self) {
         self.vote_reset = vec![];
         self.vote_cancel = vec![];
+        self.last_pick = None;
 
         if self.closing {
             let _ = self
@@ -381,6 +416,8 @@ impl BonkRoom {
     }
 
     pub async fn start_map_selection(&mut self) {
+        self.announce_prediction().await;
+
         if let Mode::Football = self.room_parameters.mode {
             self.transition_timer = Box::pin(time::sleep(Duration::from_secs(
                 self.room_parameters.ready_time,
@@ -393,20 +430,28 @@ impl BonkRoom {
 
         self.map_strikes = vec![false; self.room_parameters.maps.len()];
         self.player_strikes = vec![];
-        let map_idx = rand::rng().random_range(0..self.room_parameters.maps.len());
-        if let Some(map_strike) = self.map_strikes.get_mut(map_idx) {
-            *map_strike = true;
-        }
-        let Some(map) = self.room_parameters.maps.get(map_idx) else {
-            return;
+        self.struck_maps = vec![];
+        self.map_turn = 0;
+        self.map_pickers = match self.room_parameters.map_selection {
+            MapSelection::Strike => vec![],
+            MapSelection::Ban => self.map_sides(),
+            MapSelection::LoserPicks => {
+                let losers = self
+                    .get_in_game()
+                    .iter()
+                    .filter(|p| self.last_losers.contains(&p.id))
+                    .map(|p| p.id)
+                    .collect::<Vec<i32>>();
+                if losers.is_empty() {
+                    vec![]
+                } else {
+                    vec![losers]
+                }
+            }
         };
-        let _ = self
-            .client
-            .execute(
-                "sgrAPI.loadMap(JSON.parse(arguments[0]));",
-                vec![json!(map)],
-            )
-            .await;
+        if self.room_parameters.maps.len() < 2 {
+            self.map_pickers = vec![];
+        }
 
         for player in &mut self.queue {
             player.1.ready_cmd = false;
@@ -419,6 +464,22 @@ impl BonkRoom {
             )
             .await;
 
+        if !self.map_pickers.is_empty() {
+            self.transition_timer = Box::pin(time::sleep(Duration::from_secs(
+                self.room_parameters.strike_time,
+            )));
+            self.warning_step = 0;
+            self.state = State::MapSelection;
+            self.announce_map_turn().await;
+            return;
+        }
+
+        let map_idx = rand::rng().random_range(0..self.room_parameters.maps.len());
+        if let Some(map_strike) = self.map_strikes.get_mut(map_idx) {
+            *map_strike = true;
+        }
+        self.load_map(map_idx).await;
+
         if self.room_parameters.strike_num <= 0 || self.room_parameters.maps.len() < 2 {
             self.transition_timer = Box::pin(time::sleep(Duration::from_secs(
                 self.room_parameters.ready_time,
@@ -452,7 +513,9 @@ impl BonkRoom {
             Queue::FFA => usize::max(1, in_game.len()),
         };
 
-        if ready >= total {
+        //Bans and picks have to finish before the game can start.
+        let choosing_map = self.state == State::MapSelection && !self.map_pickers.is_empty();
+        if ready >= total && !choosing_map {
             let _ = self.client.execute("sgrAPI.startGame();", vec![]).await;
             self.transition_timer = Box::pin(time::sleep(Duration::MAX));
             self.warning_step = 0;
@@ -465,6 +528,148 @@ impl BonkRoom {
         }
     }
 
+    ///Loads a map from room_parameters.maps.
+    pub async fn load_map(&mut self, map_idx: usize) {
+        let Some(map) = self.room_parameters.maps.get(map_idx) else {
+            return;
+        };
+        self.current_map = Some(map_idx);
+        let _ = self
+            .client
+            .execute(
+                "sgrAPI.loadMap(JSON.parse(arguments[0]));",
+                vec![json!(map)],
+            )
+            .await;
+    }
+
+    ///Index and name of every map that hasn't been banned or struck.
+    pub fn remaining_maps(&self) -> Vec<(usize, String)> {
+        self.room_parameters
+            .maps
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.map_strikes.get(*i) == Some(&false))
+            .map(|(i, map)| {
+                let name = serde_json::from_str::<GameMap>(map)
+                    .map(|map| map.name)
+                    .unwrap_or_default();
+                (i, name)
+            })
+            .collect()
+    }
+
+    ///Lists the remaining maps and who bans or picks next.
+    pub async fn announce_map_turn(&mut self) {
+        let Some(side) = self
+            .map_pickers
+            .get(self.map_turn % self.map_pickers.len().max(1))
+        else {
+            return;
+        };
+        let names = self
+            .get_in_game()
+            .iter()
+            .filter(|p| side.contains(&p.id))
+            .map(|p| p.name.clone())
+            .collect::<Vec<String>>()
+            .join(" & ");
+        let maps = self
+            .remaining_maps()
+            .iter()
+            .map(|(i, name)| format!("{}. {}", i + 1, name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let action = match self.room_parameters.map_selection {
+            MapSelection::Ban => "ban",
+            _ => "pick",
+        };
+
+        self.chat(format!(
+            "{}, {} a map with !s <number>: {}",
+            names, action, maps
+        ))
+        .await;
+    }
+
+    ///Ids of the players on each side of the next game. FFA players are each their own side.
+    fn map_sides(&self) -> Vec<Vec<i32>> {
+        match &self.game_players {
+            GamePlayers::Singles {
+                picker: Some(picker),
+                picked: Some(picked),
+            } => vec![vec![picker.id], vec![picked.id]],
+            GamePlayers::Singles { .. } => vec![],
+            GamePlayers::Teams {
+                teams,
+                picker_idx: _,
+            } => teams
+                .iter()
+                .map(|team| team.iter().map(|p| p.id).collect())
+                .collect(),
+            GamePlayers::FFA { in_game } => in_game.iter().map(|p| vec![p.id]).collect(),
+        }
+    }
+
+    ///Names of every team in the next game. FFA players are each their own team.
+    pub fn game_teams(&self) -> Vec<Vec<String>> {
+        match &self.game_players {
+            GamePlayers::Singles {
+                picker: Some(picker),
+                picked: Some(picked),
+            } => vec![vec![picker.name.clone()], vec![picked.name.clone()]],
+            GamePlayers::Singles { .. } => vec![],
+            GamePlayers::Teams {
+                teams,
+                picker_idx: _,
+            } => teams
+                .iter()
+                .map(|team| team.iter().map(|p| p.name.clone()).collect())
+                .collect(),
+            GamePlayers::FFA { in_game } => in_game.iter().map(|p| vec![p.name.clone()]).collect(),
+        }
+    }
+
+    ///Announces each side's chance to win and the rating change for a win or loss when the
+    ///leaderboard's algorithm has one.
+    pub async fn announce_prediction(&mut self) {
+        let Some(leaderboard_tx) = &self.leaderboard_tx else {
+            return;
+        };
+        let teams = self.game_teams();
+        if teams.len() < 2 {
+            return;
+        }
+
+        let (prediction_tx, prediction_rx) = oneshot::channel();
+        let _ = leaderboard_tx
+            .send(LeaderboardMessage::Predict {
+                teams,
+                prediction: prediction_tx,
+            })
+            .await;
+        let Ok(Ok(prediction)) = prediction_rx.await else {
+            return;
+        };
+
+        let message = prediction
+            .iter()
+            .map(|team| {
+                let stakes = match (team.win_change, team.loss_change) {
+                    (Some(win), Some(loss)) => format!(" ({win:+.0}/{loss:+.0})"),
+                    _ => String::new(),
+                };
+                format!(
+                    "{} {:.0}%{stakes}",
+                    team.names.join(" & "),
+                    team.win_probability * 100.,
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(" vs ");
+        self.chat(message).await;
+    }
+
     async fn update(&mut self) {
         if self.state == State::Remaking {
             self.update_remake_room().await;
@@ -558,10 +763,16 @@ impl BonkRoom {
                         self.room_parameters.game_time,
                     )));
                     self.warning_step = 0;
+                    self.round_scores = vec![];
+                    self.round_start = Instant::now();
+                    self.rounds = vec![];
                     self.state = State::InGame
                 }
             }
             if let State::InGame = self.state {
+                //The final scores are still readable when the game ends, so the winning round
+                //is recorded before the game is rated.
+                self.record_rounds().await;
                 if output.get(1) == Some(&false) {
                     events::on_game_end(self, None, false).await;
                 }
@@ -657,6 +868,35 @@ impl BonkRoom {
         }
     }
 
+    ///Compares the scores with the last poll and records a round for every score that went up.
+    async fn record_rounds(&mut self) {
+        let scores = self
+            .client
+            .execute(
+                "return arguments[0] ? sgrAPI.footballState.scores : sgrAPI.state.scores;",
+                vec![json!(self.room_parameters.mode == Mode::Football)],
+            )
+            .await;
+        let Ok(scores) = scores else {
+            return;
+        };
+        let Ok(scores) = from_value::<Vec<Option<i32>>>(scores) else {
+            return;
+        };
+
+        //The first poll of a game is the starting score.
+        if !self.round_scores.is_empty() {
+            for (i, score) in scores.iter().enumerate() {
+                let old = self.round_scores.get(i).copied().flatten().unwrap_or(0);
+                if score.is_some_and(|score| score > old) {
+                    self.rounds.push((i, self.round_start.elapsed()));
+                    self.round_start = Instant::now();
+                }
+            }
+        }
+        self.round_scores = scores;
+    }
+
     pub async fn start_remake_room(&mut self) {
         if self.closing {
             self.rx.close();
@@ -768,6 +1008,7 @@ impl BonkRoom {
                     vec![json!(map)],
                 )
                 .await;
+            self.current_map = Some(0);
         }
 
         self.reset().await;
@@ -868,3 +1109,23 @@ pub fn sec_to_string(time: u64) -> String {
 
     output.join(" and ")
 }
+
+///Index of the team whose captain picks next in a draft from the size of every team,
+///captains included. None when every team is full.
+pub fn draft_picker(team_sizes: &[usize], team_size: usize, order: &DraftOrder) -> Option<usize> {
+    let team_num = team_sizes.len();
+    if team_num == 0 {
+        return None;
+    }
+
+    let picks: usize = team_sizes.iter().map(|size| size.saturating_sub(1)).sum();
+    (picks..picks + team_num * team_size)
+        .map(|pick| {
+            let idx = pick % team_num;
+            match order {
+                DraftOrder::Snake if (pick / team_num) % 2 == 1 => team_num - 1 - idx,
+                _ => idx,
+            }
+        })
+        .find(|team| team_sizes[*team] < team_size)
+}
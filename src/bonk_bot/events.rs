@@ -1,6 +1,7 @@
 use rand::{seq::SliceRandom, RngExt};
 use serde::Deserialize;
 use serde_json::{from_value, json};
+use serenity::all::ChannelId;
 use std::time::Duration;
 use tokio::{sync::oneshot, time};
 
@@ -8,9 +9,11 @@ use crate::{
     bonk_bot::{
         bonk_commands,
         bonk_room::{GamePlayers, State},
-        room_maker::{Mode, Queue},
+        room_maker::{CaptainSelection, Mode, Queue, RoomParameters, TeamFormation},
     },
-    leaderboard::LeaderboardMessage,
+    challenges::{self, ChallengeMatch},
+    leaderboard::{GameMap, GameRound, LeaderboardMessage, NewGame},
+    tournament::{TournamentKey, TournamentMatch, TournamentMessage},
 };
 
 use super::bonk_room::{BonkRoom, Player};
@@ -26,6 +29,18 @@ pub async fn on_transition_timer_expired(room: &mut BonkRoom) {
         State::Idle => transition_idle(room).await,
         State::Pick => transition_pick(room).await,
         State::MapSelection => {
+            //Bans and picks that run out of time end on a random remaining map.
+            if !room.map_pickers.is_empty() {
+                room.map_pickers = vec![];
+                let remaining_maps = room.remaining_maps();
+                if !remaining_maps.is_empty() {
+                    let map_idx = rand::rng().random_range(0..remaining_maps.len());
+                    if let Some((map_idx, _)) = remaining_maps.get(map_idx) {
+                        room.load_map(*map_idx).await;
+                    }
+                }
+            }
+
             room.transition_timer = Box::pin(time::sleep(Duration::from_secs(
                 room.room_parameters.ready_time,
             )));
@@ -148,12 +163,13 @@ async fn transition_idle(room: &mut BonkRoom) {
                 team_num = 4;
             }
             room.room_parameters.team_num = team_num;
-            if queue.len() >= room.room_parameters.team_size * room.room_parameters.team_num {
-                for i in 0..room.room_parameters.team_num {
-                    if let Some(player) = queue.get(i) {
-                        captains.push(player.clone());
-                    }
+            let player_num = room.room_parameters.team_size * room.room_parameters.team_num;
+            match teams_start(&room.room_parameters, queue.len()) {
+                TeamsStart::Balance => {
+                    balance_teams(room, queue.into_iter().take(player_num).collect()).await;
                 }
+                TeamsStart::Draft => captains = draft_captains(room, &queue).await,
+                TeamsStart::Wait => (),
             }
 
             if captains.len() == room.room_parameters.team_num {
@@ -197,7 +213,8 @@ async fn transition_idle(room: &mut BonkRoom) {
                     ))
                     .await;
                 }
-            } else {
+            } else if let State::Idle = room.state {
+                //Balanced teams already started map selection with its own timer.
                 room.transition_timer = Box::pin(time::sleep(Duration::from_secs(
                     room.room_parameters.idle_time,
                 )));
@@ -248,6 +265,115 @@ async fn transition_idle(room: &mut BonkRoom) {
     };
 }
 
+///How the Teams queue starts its next game.
+#[derive(PartialEq, Debug)]
+pub enum TeamsStart {
+    ///Split the first players into balanced teams and go straight to map selection.
+    Balance,
+    ///Captains pick their teammates.
+    Draft,
+    ///There aren't enough players yet, so the room checks again after idle_time.
+    Wait,
+}
+
+pub fn teams_start(room_parameters: &RoomParameters, queue_len: usize) -> TeamsStart {
+    if queue_len < room_parameters.team_size * room_parameters.team_num {
+        TeamsStart::Wait
+    } else if room_parameters.team_formation == TeamFormation::Balanced {
+        TeamsStart::Balance
+    } else {
+        TeamsStart::Draft
+    }
+}
+
+///Captains of a draft in pick order. Captains chosen by rating fall back to the first
+///players in the queue when the room has no leaderboard.
+async fn draft_captains(room: &BonkRoom, queue: &[Player]) -> Vec<Player> {
+    let team_num = room.room_parameters.team_num;
+    let first = queue.iter().take(team_num).cloned().collect();
+    let (CaptainSelection::Rating, Some(leaderboard_tx)) =
+        (&room.room_parameters.captains, &room.leaderboard_tx)
+    else {
+        return first;
+    };
+
+    let (ratings_tx, ratings_rx) = oneshot::channel();
+    let _ = leaderboard_tx
+        .send(LeaderboardMessage::Ratings {
+            names: queue.iter().map(|p| p.name.clone()).collect(),
+            ratings: ratings_tx,
+        })
+        .await;
+    let Ok(Ok(ratings)) = ratings_rx.await else {
+        return first;
+    };
+
+    //The lowest rated captain picks first.
+    let mut rated = queue
+        .iter()
+        .cloned()
+        .zip(ratings)
+        .collect::<Vec<(Player, f64)>>();
+    rated.sort_by(|a, b| b.1.total_cmp(&a.1));
+    rated.truncate(team_num);
+    rated.reverse();
+    rated.into_iter().map(|(player, _)| player).collect()
+}
+
+///Splits players into teams with the closest chances to win on the room's leaderboard and
+///starts map selection. Rooms without a leaderboard alternate players between teams.
+async fn balance_teams(room: &mut BonkRoom, players: Vec<Player>) {
+    let team_num = room.room_parameters.team_num;
+    let mut split = None;
+    if let Some(leaderboard_tx) = &room.leaderboard_tx {
+        let (teams_tx, teams_rx) = oneshot::channel();
+        let _ = leaderboard_tx
+            .send(LeaderboardMessage::Balance {
+                players: players.iter().map(|p| p.name.clone()).collect(),
+                team_num,
+                teams: teams_tx,
+            })
+            .await;
+        split = teams_rx.await.ok().and_then(|teams| teams.ok());
+    }
+    let split = split.unwrap_or_else(|| {
+        (0..team_num)
+            .map(|team| (team..players.len()).step_by(team_num).collect())
+            .collect()
+    });
+    let teams = split
+        .iter()
+        .map(|team| {
+            team.iter()
+                .filter_map(|i| players.get(*i).cloned())
+                .collect()
+        })
+        .collect::<Vec<Vec<Player>>>();
+
+    if let Mode::Football = room.room_parameters.mode {
+        room.team_flip = rand::rng().random();
+    }
+    for (i, team) in teams.iter().enumerate() {
+        let bonk_team = match room.room_parameters.mode {
+            Mode::Football if (i == 0) != room.team_flip => 2,
+            Mode::Football => 3,
+            _ => 2 + i,
+        };
+        for player in team {
+            let _ = room.client.execute(
+                "sgrAPI.toolFunctions.networkEngine.changeOtherTeam(arguments[0], arguments[1]);",
+                vec![json!(player.id), json!(bonk_team)]
+            ).await;
+        }
+    }
+
+    room.game_players = GamePlayers::Teams {
+        teams,
+        picker_idx: 0,
+    };
+    room.start_map_selection().await;
+}
+
 async fn transition_pick(room: &mut BonkRoom) {
     match &room.game_players {
         GamePlayers::Singles { picker, picked: _ } => {
@@ -360,13 +486,17 @@ pub async fn on_message(room: &mut BonkRoom, message: String) {
                         bonk_commands::leaderboard(room, command.join(" ")).await
                     }
                     "elo" | "e" => bonk_commands::elo(room, id, command.join(" ")).await,
+                    "history" => bonk_commands::history(room, id, command.join(" ")).await,
+                    "h2h" => bonk_commands::h2h(room, id, command.join(" ")).await,
                     "pick" | "p" => bonk_commands::pick(room, id, command.join(" ")).await,
                     "any" | "a" => bonk_commands::any(room, id).await,
-                    "strike" | "s" => bonk_commands::strike(room, id).await,
+                    "undo" | "u" => bonk_commands::undo(room, id).await,
+                    "strike" | "s" => bonk_commands::strike(room, id, command.join(" ")).await,
                     "ready" | "r" => bonk_commands::ready(room, id).await,
                     "skip" | "sk" => bonk_commands::skip(room, id).await,
                     "reset" | "re" => bonk_commands::reset(room, id).await,
                     "cancel" | "c" => bonk_commands::cancel(room, id).await,
+                    "link" => bonk_commands::link(room, id, command.join(" ")).await,
                     _ => room.chat(help_string).await,
                 }
             }
@@ -398,6 +528,27 @@ pub async fn on_message(room: &mut BonkRoom, message: String) {
 }
 
 pub async fn on_player_join(room: &mut BonkRoom, player: Player) {
+    let whitelist = &room.room_parameters.whitelist;
+    if !whitelist.is_empty()
+        && !whitelist
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&player.name))
+    {
+        let _ = room
+            .client
+            .execute(
+                "sgrAPI.toolFunctions.networkEngine.kickPlayer(arguments[0]);",
+                vec![json!(player.id)],
+            )
+            .await;
+
+        let idx = room.queue.iter().position(|p| p.1.id == player.id);
+        if let Some(idx) = idx {
+            room.queue.remove(idx);
+        }
+        return;
+    }
+
     match &mut room.game_players {
         GamePlayers::Singles { picker, picked } => {
             let mut game_player = None;
@@ -633,6 +784,9 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
         score: i32,
     }
 
+    let forced_winner = winner.filter(|winner| *winner != 0);
+    room.last_losers = vec![];
+
     match &room.game_players {
         GamePlayers::Singles { picker, picked } => {
             let Some(picked) = picked else {
@@ -653,68 +807,97 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                 room.queue.push(queue_spot);
             }
 
-            if let Some(leaderboard_tx) = &room.leaderboard_tx {
-                if tie {
-                    let (match_string_tx, match_string_rx) = oneshot::channel();
-                    let _ = leaderboard_tx
-                        .send(LeaderboardMessage::Update {
-                            teams: vec![vec![picker.name.clone()], vec![picked.name.clone()]],
-                            ties: vec![true],
-                            match_str: match_string_tx,
-                        })
-                        .await;
-                    if let Ok(Ok(match_string)) = match_string_rx.await {
-                        room.chat(match_string).await;
-                    }
+            //Football rounds are scored by team and other rounds by player id.
+            let football = room.room_parameters.mode == Mode::Football;
+            let key_of = |player: &Player| {
+                if !football {
+                    player.id as usize
+                } else if (player.id == picker.id) ^ room.team_flip {
+                    2
                 } else {
-                    if let Mode::Football = room.room_parameters.mode {
-                        if !winner.is_some() {
-                            let value = room
-                                .client
-                                .execute(
-                                    "\
-                                if(sgrAPI.footballState.scores[3] > sgrAPI.footballState.scores[2]) return 3;\
-                                if(sgrAPI.footballState.scores[2] < sgrAPI.footballState.scores[3]) return 2;\
-                                return 0;\
-                                ",
-                                    vec![],
-                                )
-                                .await;
-                            if let Ok(value) = value {
-                                if let Ok(value) = from_value::<usize>(value) {
-                                    winner = Some(value);
-                                }
-                            }
+                    3
+                }
+            };
+            //The picker starts on the first side outside of football.
+            let side_of = |player: &Player| {
+                if football {
+                    Some(key_of(player) as i32 - 2)
+                } else if player.id == picker.id {
+                    Some(0)
+                } else {
+                    Some(1)
+                }
+            };
+
+            //Losers are found even in unrated rooms since LoserPicks map selection uses them.
+            if tie {
+                let game = new_game(
+                    room,
+                    vec![vec![picker.name.clone()], vec![picked.name.clone()]],
+                    vec![true],
+                    None,
+                    &[key_of(picker), key_of(picked)],
+                    vec![side_of(picker), side_of(picked)],
+                );
+                rate_game(room, game).await;
+            } else {
+                if let Mode::Football = room.room_parameters.mode {
+                    let scores = room
+                        .client
+                        .execute("return sgrAPI.footballState.scores;", vec![])
+                        .await
+                        .ok()
+                        .and_then(|scores| from_value::<Vec<Option<i32>>>(scores).ok());
+                    //Red is team 2 and blue is team 3.
+                    let mut red = scores
+                        .as_ref()
+                        .and_then(|scores| scores.get(2).copied().flatten());
+                    let mut blue = scores
+                        .as_ref()
+                        .and_then(|scores| scores.get(3).copied().flatten());
+                    if !winner.is_some() {
+                        if let (Some(red), Some(blue)) = (red, blue) {
+                            winner = Some(match red.cmp(&blue) {
+                                std::cmp::Ordering::Greater => 2,
+                                std::cmp::Ordering::Less => 3,
+                                std::cmp::Ordering::Equal => 0,
+                            });
                         }
-                        if let Some(winner) = winner {
-                            let (match_string_tx, match_string_rx) = oneshot::channel();
-                            let _ = leaderboard_tx
-                                .send(LeaderboardMessage::Update {
-                                    teams: vec![
-                                        vec![if (winner == 2 || winner == 0) ^ room.team_flip {
-                                            picker.name.clone()
-                                        } else {
-                                            picked.name.clone()
-                                        }],
-                                        vec![if (winner == 3) ^ room.team_flip {
-                                            picker.name.clone()
-                                        } else {
-                                            picked.name.clone()
-                                        }],
-                                    ],
-                                    ties: vec![winner == 0],
-                                    match_str: match_string_tx,
-                                })
-                                .await;
-                            if let Ok(Ok(match_string)) = match_string_rx.await {
-                                room.chat(match_string).await;
+                    }
+                    match forced_winner {
+                        Some(2) => red = Some(room.room_parameters.rounds),
+                        Some(3) => blue = Some(room.room_parameters.rounds),
+                        _ => (),
+                    }
+                    if let Some(winner) = winner {
+                        let (first, second) = if (winner == 2 || winner == 0) ^ room.team_flip {
+                            (picker, picked)
+                        } else {
+                            (picked, picker)
+                        };
+                        let score = match (red, blue) {
+                            (Some(red), Some(blue)) if winner == 3 => {
+                                Some(vec![f64::from(blue), f64::from(red)])
                             }
-                        }
-                    } else {
-                        let scores = room
-                            .client
-                            .execute(
-                                "\
+                            (Some(red), Some(blue)) => Some(vec![f64::from(red), f64::from(blue)]),
+                            _ => None,
+                        };
+                        let game = new_game(
+                            room,
+                            vec![vec![first.name.clone()], vec![second.name.clone()]],
+                            vec![winner == 0],
+                            score,
+                            &[key_of(first), key_of(second)],
+                            vec![side_of(first), side_of(second)],
+                        );
+                        room.last_losers = losing_ids(room, &game);
+                        rate_game(room, game).await;
+                    }
+                } else {
+                    let scores = room
+                        .client
+                        .execute(
+                            "\
                                 return Object.keys(sgrAPI.state.scores)\
                                     .map(id => {\
                                         let score = sgrAPI.state.scores[id];\
@@ -722,57 +905,61 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                                         return {id: Number(id), score};\
                                     }).filter(x => x !== undefined);\
                             ",
-                                vec![],
-                            )
-                            .await;
-                        if let Ok(scores) = scores {
-                            if let Ok(scores) = from_value::<Vec<Score>>(scores) {
-                                if !winner.is_some() {
-                                    'winner: {
-                                        if winner != None {
-                                            break 'winner;
-                                        };
-                                        let Some(p1) = scores.get(0) else {
-                                            break 'winner;
-                                        };
-                                        let Some(p2) = scores.get(1) else {
-                                            break 'winner;
-                                        };
-
-                                        if p1.score > p2.score {
-                                            winner = Some(p1.id as usize);
-                                        } else if p2.score > p1.score {
-                                            winner = Some(p2.id as usize)
-                                        } else {
-                                            winner = Some(0);
-                                        }
+                            vec![],
+                        )
+                        .await;
+                    if let Ok(scores) = scores {
+                        if let Ok(scores) = from_value::<Vec<Score>>(scores) {
+                            if !winner.is_some() {
+                                'winner: {
+                                    if winner != None {
+                                        break 'winner;
+                                    };
+                                    let Some(p1) = scores.get(0) else {
+                                        break 'winner;
+                                    };
+                                    let Some(p2) = scores.get(1) else {
+                                        break 'winner;
+                                    };
+
+                                    if p1.score > p2.score {
+                                        winner = Some(p1.id as usize);
+                                    } else if p2.score > p1.score {
+                                        winner = Some(p2.id as usize)
+                                    } else {
+                                        winner = Some(0);
                                     }
                                 }
-                                if let Some(winner) = winner {
-                                    let picker_lostnt = winner as i32 == picker.id || winner == 0;
-                                    let (match_string_tx, match_string_rx) = oneshot::channel();
-                                    let _ = leaderboard_tx
-                                        .send(LeaderboardMessage::Update {
-                                            teams: vec![
-                                                vec![if picker_lostnt {
-                                                    picker.name.clone()
-                                                } else {
-                                                    picked.name.clone()
-                                                }],
-                                                vec![if !picker_lostnt {
-                                                    picker.name.clone()
-                                                } else {
-                                                    picked.name.clone()
-                                                }],
-                                            ],
-                                            ties: vec![winner == 0],
-                                            match_str: match_string_tx,
-                                        })
-                                        .await;
-                                    if let Ok(Ok(match_string)) = match_string_rx.await {
-                                        room.chat(match_string).await;
+                            }
+                            if let Some(winner) = winner {
+                                let picker_lostnt = winner as i32 == picker.id || winner == 0;
+                                let score_of = |id: i32| {
+                                    if forced_winner == Some(id as usize) {
+                                        return Some(f64::from(room.room_parameters.rounds));
                                     }
-                                }
+                                    scores
+                                        .iter()
+                                        .find(|score| score.id == id)
+                                        .map(|score| f64::from(score.score))
+                                };
+                                let (first, second) = if picker_lostnt {
+                                    (picker, picked)
+                                } else {
+                                    (picked, picker)
+                                };
+                                let score = score_of(first.id)
+                                    .zip(score_of(second.id))
+                                    .map(|(first, second)| vec![first, second]);
+                                let game = new_game(
+                                    room,
+                                    vec![vec![first.name.clone()], vec![second.name.clone()]],
+                                    vec![winner == 0],
+                                    score,
+                                    &[key_of(first), key_of(second)],
+                                    vec![side_of(first), side_of(second)],
+                                );
+                                room.last_losers = losing_ids(room, &game);
+                                rate_game(room, game).await;
                             }
                         }
                     }
@@ -817,11 +1004,8 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                 }
             }
 
-            'lb: {
-                let Some(leaderboard_tx) = &room.leaderboard_tx else {
-                    break 'lb;
-                };
-
+            //Losers are found even in unrated rooms since LoserPicks map selection uses them.
+            'game: {
                 let scores;
                 if let Mode::Football = room.room_parameters.mode {
                     scores = room
@@ -840,9 +1024,9 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                         )
                         .await;
                 }
-                let Ok(scores) = scores else { break 'lb };
+                let Ok(scores) = scores else { break 'game };
                 let Ok(mut scores) = from_value::<Vec<i32>>(scores) else {
-                    break 'lb;
+                    break 'game;
                 };
 
                 if let Some(winner) = winner {
@@ -851,6 +1035,14 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                     }
                 }
 
+                //Scores skip empty teams so the nth score belongs to the nth non-empty team.
+                let keys = room
+                    .round_scores
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, score)| score.is_some())
+                    .map(|(i, _)| i)
+                    .collect::<Vec<usize>>();
                 let mut placements = teams
                     .iter()
                     .enumerate()
@@ -858,9 +1050,11 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                         (
                             scores.get(i),
                             team.iter().map(|p| p.name.clone()).collect::<Vec<String>>(),
+                            keys.get(i).copied().unwrap_or(usize::MAX),
+                            i as i32,
                         )
                     })
-                    .collect::<Vec<(Option<&i32>, Vec<String>)>>();
+                    .collect::<Vec<(Option<&i32>, Vec<String>, usize, i32)>>();
                 placements.sort_by(|team1, team2| team2.0.cmp(&team1.0));
                 let mut ties;
                 if tie {
@@ -883,18 +1077,21 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                     .iter()
                     .map(|team| team.1.clone())
                     .collect::<Vec<Vec<String>>>();
-
-                let (match_string_tx, match_string_rx) = oneshot::channel();
-                let _ = leaderboard_tx
-                    .send(LeaderboardMessage::Update {
-                        teams: team_strings,
-                        ties,
-                        match_str: match_string_tx,
-                    })
-                    .await;
-                if let Ok(Ok(match_string)) = match_string_rx.await {
-                    room.chat(match_string).await;
-                }
+                let score = placements
+                    .iter()
+                    .map(|team| team.0.map(|score| f64::from(*score)))
+                    .collect::<Option<Vec<f64>>>()
+                    .filter(|_| !tie);
+                let game = new_game(
+                    room,
+                    team_strings,
+                    ties,
+                    score,
+                    &placements.iter().map(|team| team.2).collect::<Vec<usize>>(),
+                    placements.iter().map(|team| Some(team.3)).collect(),
+                );
+                room.last_losers = losing_ids(room, &game);
+                rate_game(room, game).await;
             }
         }
         GamePlayers::FFA { in_game } => {
@@ -906,11 +1103,8 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                 }
             }
 
-            'lb: {
-                let Some(leaderboard_tx) = &room.leaderboard_tx else {
-                    break 'lb;
-                };
-
+            //Losers are found even in unrated rooms since LoserPicks map selection uses them.
+            'game: {
                 let scores = room
                     .client
                     .execute(
@@ -925,9 +1119,9 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                         vec![json!(room.room_parameters.rounds)],
                     )
                     .await;
-                let Ok(scores) = scores else { break 'lb };
+                let Ok(scores) = scores else { break 'game };
                 let Ok(mut scores) = from_value::<Vec<Score>>(scores) else {
-                    break 'lb;
+                    break 'game;
                 };
 
                 if let Some(winner) = winner {
@@ -942,16 +1136,17 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                         (
                             scores.iter().find(|score| score.id == player.id),
                             vec![player.name.clone()],
+                            player.id as usize,
                         )
                     })
                     .map(|placement| {
                         if let Some(score) = placement.0 {
-                            return (score.score, placement.1);
+                            return (score.score, placement.1, placement.2);
                         } else {
-                            return (0, placement.1);
+                            return (0, placement.1, placement.2);
                         }
                     })
-                    .collect::<Vec<(i32, Vec<String>)>>();
+                    .collect::<Vec<(i32, Vec<String>, usize)>>();
                 placements.sort_by(|p1, p2| p2.0.cmp(&p1.0));
                 let mut ties;
                 if tie {
@@ -974,21 +1169,182 @@ pub async fn on_game_end(room: &mut BonkRoom, mut winner: Option<usize>, tie: bo
                     .iter()
                     .map(|player| player.1.clone())
                     .collect::<Vec<Vec<String>>>();
-
-                let (match_string_tx, match_string_rx) = oneshot::channel();
-                let _ = leaderboard_tx
-                    .send(LeaderboardMessage::Update {
-                        teams: team_strings,
-                        ties,
-                        match_str: match_string_tx,
-                    })
-                    .await;
-                if let Ok(Ok(match_string)) = match_string_rx.await {
-                    room.chat(match_string).await;
-                }
+                let score = (!tie).then(|| {
+                    placements
+                        .iter()
+                        .map(|player| f64::from(player.0))
+                        .collect::<Vec<f64>>()
+                });
+                let game = new_game(
+                    room,
+                    team_strings,
+                    ties,
+                    score,
+                    &placements
+                        .iter()
+                        .map(|player| player.2)
+                        .collect::<Vec<usize>>(),
+                    vec![None; placements.len()],
+                );
+                room.last_losers = losing_ids(room, &game);
+                rate_game(room, game).await;
             }
         }
     }
 
+    if let Some(tournament_match) = room.room_parameters.tournament_match.clone() {
+        report_tournament_match(room, tournament_match).await;
+    }
+    if let Some(challenge) = room.room_parameters.challenge.clone() {
+        finish_challenge(room, challenge).await;
+    }
+    if room.room_parameters.one_game && !room.closing {
+        room.chat("This room closes after one game. GG!".to_string())
+            .await;
+        room.closing = true;
+    }
+
     room.reset().await;
 }
+
+///Rates a game if the room has a leaderboard and posts the result.
+async fn rate_game(room: &mut BonkRoom, game: NewGame) {
+    let Some(leaderboard_tx) = &room.leaderboard_tx else {
+        return;
+    };
+
+    let (match_string_tx, match_string_rx) = oneshot::channel();
+    let _ = leaderboard_tx
+        .send(LeaderboardMessage::Update {
+            game,
+            match_str: match_string_tx,
+        })
+        .await;
+    if let Ok(Ok(match_string)) = match_string_rx.await {
+        post_match_string(room, match_string).await;
+    }
+}
+
+///Chats a rated game's result and posts it to the challenge thread if the room has one.
+async fn post_match_string(room: &mut BonkRoom, match_string: String) {
+    if let Some(challenge) = &room.room_parameters.challenge {
+        if challenge.thread != 0 {
+            let _ = ChannelId::new(challenge.thread)
+                .say(&room.http, &match_string)
+                .await;
+        }
+    }
+    room.chat(match_string).await;
+}
+
+///Marks a challenge as played and closes the room. Tied games are replayed.
+async fn finish_challenge(room: &mut BonkRoom, challenge: ChallengeMatch) {
+    if room.last_losers.is_empty() {
+        room.chat("There's no winner yet, so the match will be replayed.".to_string())
+            .await;
+        return;
+    }
+
+    let db = {
+        let data = room.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    };
+    if let Some(db) = db {
+        if let Err(e) = challenges::finish(&db.db, challenge.id).await {
+            println!("Failed to finish challenge: {e}");
+        }
+    }
+
+    room.chat("The result has been posted to the challenge thread. GG!".to_string())
+        .await;
+    room.closing = true;
+}
+
+///Sends the winner of a tournament match to the bracket and closes the room. Tied games are
+///replayed.
+async fn report_tournament_match(room: &mut BonkRoom, tournament_match: TournamentMatch) {
+    let losers = room
+        .queue
+        .iter()
+        .filter(|p| room.last_losers.contains(&p.1.id))
+        .map(|p| p.1.name.clone())
+        .collect::<Vec<String>>();
+    let loser_side = tournament_match.players.iter().position(|(_, names)| {
+        names
+            .iter()
+            .any(|name| losers.iter().any(|loser| loser.eq_ignore_ascii_case(name)))
+    });
+    let Some((winner, _)) = loser_side.and_then(|side| tournament_match.players.get(1 - side))
+    else {
+        room.chat("There's no winner yet, so the match will be replayed.".to_string())
+            .await;
+        return;
+    };
+
+    let tournament_tx = {
+        let data = room.data.read().await;
+        data.get::<TournamentKey>().cloned()
+    };
+    if let Some(tournament_tx) = tournament_tx {
+        let _ = tournament_tx
+            .send(TournamentMessage::Result {
+                tournament_id: tournament_match.tournament_id,
+                match_idx: tournament_match.match_idx,
+                winner: *winner,
+            })
+            .await;
+    }
+
+    room.chat("The result has been sent to the bracket. GG!".to_string())
+        .await;
+    room.closing = true;
+}
+
+///Ids of the players on the last team of a game unless it tied with the team before it.
+fn losing_ids(room: &BonkRoom, game: &NewGame) -> Vec<i32> {
+    if game.ties.last() != Some(&false) {
+        return vec![];
+    }
+    let Some(losers) = game.teams.last() else {
+        return vec![];
+    };
+
+    room.get_in_game()
+        .iter()
+        .filter(|p| losers.contains(&p.name))
+        .map(|p| p.id)
+        .collect()
+}
+
+///A rated game with the rounds and maps recorded by the room. Keys are the score index of
+///every team in placement order and sides are the side each team started on.
+fn new_game(
+    room: &BonkRoom,
+    teams: Vec<Vec<String>>,
+    ties: Vec<bool>,
+    score: Option<Vec<f64>>,
+    keys: &[usize],
+    sides: Vec<Option<i32>>,
+) -> NewGame {
+    let map_info = |idx: &usize| {
+        let map = room.room_parameters.maps.get(*idx)?;
+        serde_json::from_str::<GameMap>(map).ok()
+    };
+
+    NewGame {
+        teams,
+        ties,
+        score,
+        rounds: room
+            .rounds
+            .iter()
+            .map(|(key, duration)| GameRound {
+                winner: keys.iter().position(|team_key| team_key == key),
+                duration: *duration,
+            })
+            .collect(),
+        map: room.current_map.as_ref().and_then(map_info),
+        struck_maps: room.struck_maps.iter().filter_map(map_info).collect(),
+        sides,
+    }
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use serenity::all::Http;
 use serenity::prelude::TypeMap;
+use sqlx::{Pool, Postgres};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::fs::File;
@@ -13,7 +14,10 @@ use tokio::sync::oneshot;
 use tokio::sync::{mpsc, RwLock};
 use tokio::time::{sleep, Instant};
 
+use crate::challenges::ChallengeMatch;
 use crate::leaderboard::LeaderboardMessage;
+use crate::maps;
+use crate::tournament::TournamentMatch;
 
 use super::bonk_room::{BonkRoom, BonkRoomMessage};
 
@@ -38,7 +42,18 @@ pub struct RoomParameters {
     pub mode: Mode,
     pub queue: Queue,
     pub rounds: i32,
+    #[serde(default)]
     pub maps: Vec<String>,
+    ///Name of a map pool from the map library. Its maps are added after maps.
+    pub map_pool: Option<String>,
+    #[serde(default)]
+    pub map_selection: MapSelection,
+    #[serde(default)]
+    pub team_formation: TeamFormation,
+    #[serde(default)]
+    pub draft_order: DraftOrder,
+    #[serde(default)]
+    pub captains: CaptainSelection,
 
     #[serde(default = "strike_num_default")]
     pub strike_num: u32,
@@ -67,6 +82,32 @@ pub struct RoomParameters {
     #[serde(default = "default_unlisted")]
     pub unlisted: bool,
     pub leaderboard: Option<String>,
+    ///Bonk names of the only players allowed in the room. Anyone can join when it's empty.
+    #[serde(default)]
+    pub whitelist: Vec<String>,
+    ///Closes the room after its first game.
+    #[serde(default)]
+    pub one_game: bool,
+    ///Set on rooms opened for a tournament match.
+    #[serde(skip)]
+    pub tournament_match: Option<TournamentMatch>,
+    ///Set on rooms opened for a challenge.
+    #[serde(skip)]
+    pub challenge: Option<ChallengeMatch>,
+}
+
+impl RoomParameters {
+    ///Adds the maps of the room's map pool to its maps.
+    pub async fn resolve_map_pool(&mut self, db: &Pool<Postgres>) -> Result<()> {
+        if let Some(pool) = &self.map_pool {
+            self.maps.extend(maps::pool_maps(db, pool).await?);
+        }
+        if self.maps.is_empty() && self.mode != Mode::Football {
+            return Err(anyhow!("The room has no maps."));
+        }
+
+        Ok(())
+    }
 }
 
 fn strike_num_default() -> u32 {
@@ -117,6 +158,56 @@ pub enum Mode {
     Classic,
 }
 
+///How the map of each game is chosen.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum MapSelection {
+    ///Every strike loads a random map that hasn't been struck.
+    #[default]
+    Strike,
+    ///Sides take turns banning maps from a list until one is left.
+    Ban,
+    ///The losing side of the last game picks the map. Falls back to strikes when nobody
+    ///in the game lost the last game, which is usual in Singles rooms with more than two
+    ///players since the queue moves both players to the back.
+    LoserPicks,
+}
+
+///How players are put into teams in the Teams queue.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum TeamFormation {
+    ///Captains pick teammates from the queue.
+    #[default]
+    #[serde(alias = "draft")]
+    Draft,
+    ///The next players in the queue are split into teams with the closest chances to win.
+    #[serde(alias = "balanced")]
+    Balanced,
+}
+
+///The order captains pick teammates in.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum DraftOrder {
+    ///Captains take turns in the same order every round.
+    #[default]
+    #[serde(alias = "straight")]
+    Straight,
+    ///The order reverses every round, e.g. ABBA for two captains.
+    #[serde(alias = "snake", alias = "ABBA", alias = "abba")]
+    Snake,
+}
+
+///How the captains of a draft are chosen.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum CaptainSelection {
+    ///The first players in the queue.
+    #[default]
+    #[serde(alias = "queue")]
+    Queue,
+    ///The highest rated players in the queue. The lowest rated captain picks first.
+    #[serde(alias = "rating")]
+    Rating,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub enum Queue {
     Singles,
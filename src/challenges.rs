@@ -0,0 +1,313 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use rand::{distr::Alphanumeric, RngExt};
+use serenity::all::{ChannelId, CreateMessage, UserId};
+use sqlx::{types::time::OffsetDateTime, Pool, Postgres};
+use tokio::time;
+
+use crate::bonk_bot::room_maker::{Queue, RoomParameters};
+use crate::bonk_bot::BonkBotKey;
+use crate::players;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const PASSWORD_LENGTH: usize = 6;
+const MAX_DELAY_DAYS: u64 = 30;
+
+///A challenge played in a bonk room. Results are posted to the challenge's thread.
+#[derive(Clone, Debug)]
+pub struct ChallengeMatch {
+    pub id: i64,
+    pub thread: u64,
+}
+
+///A challenge waiting for an answer.
+pub struct Challenge {
+    pub id: i64,
+    pub lb: String,
+    pub time: Option<OffsetDateTime>,
+}
+
+///Checks for accepted challenges that are due and opens their rooms.
+pub fn start(ctx: serenity::all::Context) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = open_due(&ctx).await {
+                println!("Failed to open challenge rooms: {e}");
+            }
+        }
+    });
+}
+
+///Creates a challenge between two linked Discord accounts. The leaderboard needs a room
+///template for the challenge room.
+pub async fn create(
+    db: &Pool<Postgres>,
+    challenger: u64,
+    opponent: u64,
+    lb: &str,
+    time: Option<OffsetDateTime>,
+) -> Result<Challenge> {
+    if challenger == opponent {
+        return Err(anyhow!("You can't challenge yourself."));
+    }
+    linked_name(db, challenger)
+        .await?
+        .context("Link your bonk.io account with \"/elo link\" first.")?;
+    linked_name(db, opponent)
+        .await?
+        .context("That player hasn't linked a bonk.io account.")?;
+
+    let (lb_id, has_room): (i64, bool) =
+        sqlx::query_as("SELECT id, room IS NOT NULL FROM leaderboard WHERE abbreviation = $1")
+            .bind(lb)
+            .fetch_optional(db)
+            .await?
+            .context("Leaderboard not found.")?;
+    if !has_room {
+        return Err(anyhow!(
+            "{} doesn't have a room template. An admin can add one with \"a lb room set\".",
+            lb
+        ));
+    }
+
+    let id = sqlx::query_scalar(
+        "INSERT INTO challenges (lb_id, challenger, opponent, time) VALUES ($1, $2, $3, $4) \
+        RETURNING id",
+    )
+    .bind(lb_id)
+    .bind(challenger as i64)
+    .bind(opponent as i64)
+    .bind(time)
+    .fetch_one(db)
+    .await?;
+
+    Ok(Challenge {
+        id,
+        lb: lb.to_string(),
+        time,
+    })
+}
+
+pub async fn set_thread(db: &Pool<Postgres>, id: i64, thread: u64) -> Result<()> {
+    sqlx::query("UPDATE challenges SET thread = $1 WHERE id = $2")
+        .bind(thread as i64)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+///Accepts or declines a challenge. Only the challenged player can accept and either player
+///can decline. Returns the message that replaces the challenge.
+pub async fn answer(db: &Pool<Postgres>, id: i64, user: u64, accept: bool) -> Result<String> {
+    let (challenger, opponent, status, time): (i64, i64, String, Option<OffsetDateTime>) =
+        sqlx::query_as("SELECT challenger, opponent, status, time FROM challenges WHERE id = $1")
+            .bind(id)
+            .fetch_optional(db)
+            .await?
+            .context("That challenge doesn't exist anymore.")?;
+    if status != "pending" {
+        return Err(anyhow!("That challenge was already answered."));
+    }
+    let user = user as i64;
+    if user != opponent && (accept || user != challenger) {
+        return Err(anyhow!("This challenge isn't for you."));
+    }
+
+    //The status is checked again so an accept and a decline can't both go through.
+    let updated = sqlx::query(
+        "UPDATE challenges SET status = $1, time = COALESCE(time, NOW()) \
+        WHERE id = $2 AND status = 'pending'",
+    )
+    .bind(if accept { "accepted" } else { "declined" })
+    .bind(id)
+    .execute(db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(anyhow!("That challenge was already answered."));
+    }
+
+    Ok(if !accept {
+        format!("<@{}> declined <@{}>'s challenge.", user, challenger)
+    } else if let Some(time) = time {
+        format!(
+            "<@{}> accepted <@{}>'s challenge! The room opens <t:{}:R>.",
+            opponent,
+            challenger,
+            time.unix_timestamp()
+        )
+    } else {
+        format!(
+            "<@{}> accepted <@{}>'s challenge! The room is opening now.",
+            opponent, challenger
+        )
+    })
+}
+
+///Opens the rooms of accepted challenges whose time has come and sends both players the
+///link and password.
+pub async fn open_due(ctx: &serenity::all::Context) -> Result<()> {
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let due: Vec<(i64, i64, i64, Option<i64>, String, String)> = sqlx::query_as(
+        "UPDATE challenges c SET status = 'opened' FROM leaderboard l \
+        WHERE l.id = c.lb_id AND c.status = 'accepted' AND c.time <= NOW() \
+        RETURNING c.id, c.challenger, c.opponent, c.thread, l.abbreviation, l.room",
+    )
+    .fetch_all(db.db.as_ref())
+    .await?;
+
+    for (id, challenger, opponent, thread, lb, room) in due {
+        let thread = thread.map(|thread| ChannelId::new(thread as u64));
+        let result = open_room(
+            ctx,
+            &db.db,
+            ChallengeMatch {
+                id,
+                thread: thread.map(|thread| thread.get()).unwrap_or_default(),
+            },
+            [challenger as u64, opponent as u64],
+            &lb,
+            &room,
+        )
+        .await;
+
+        let message = match result {
+            Ok(()) => {
+                "The room is open! The link and password were sent to both players.".to_string()
+            }
+            Err(e) => {
+                sqlx::query("UPDATE challenges SET status = 'failed' WHERE id = $1")
+                    .bind(id)
+                    .execute(db.db.as_ref())
+                    .await?;
+                format!("Failed to make room: {}", e)
+            }
+        };
+        if let Some(thread) = thread {
+            let _ = thread.say(&ctx.http, message).await;
+        }
+    }
+
+    Ok(())
+}
+
+///Marks a challenge as played.
+pub async fn finish(db: &Pool<Postgres>, id: i64) -> Result<()> {
+    sqlx::query("UPDATE challenges SET status = 'played' WHERE id = $1")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+///Reads a challenge time. Takes a Discord timestamp like <t:1700000000:f> or a delay like 30m,
+///2h or 1d12h of at most MAX_DELAY_DAYS.
+pub fn parse_time(time: &str) -> Option<OffsetDateTime> {
+    if let Some(timestamp) = time
+        .strip_prefix("<t:")
+        .and_then(|time| time.strip_suffix('>'))
+    {
+        let timestamp = timestamp.split(':').next().unwrap_or(timestamp);
+        return OffsetDateTime::from_unix_timestamp(timestamp.parse().ok()?).ok();
+    }
+
+    let mut delay: u64 = 0;
+    let mut number = String::new();
+    for c in time.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 60 * 60 * 24,
+            'h' => 60 * 60,
+            'm' => 60,
+            _ => return None,
+        };
+        delay = delay.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() || delay == 0 || delay > MAX_DELAY_DAYS * 60 * 60 * 24 {
+        return None;
+    }
+
+    OffsetDateTime::now_utc().checked_add(::time::Duration::seconds(delay as i64))
+}
+
+async fn open_room(
+    ctx: &serenity::all::Context,
+    db: &Pool<Postgres>,
+    challenge: ChallengeMatch,
+    players: [u64; 2],
+    lb: &str,
+    room: &str,
+) -> Result<()> {
+    let mut names = vec![];
+    for player in players {
+        names.push(
+            linked_name(db, player)
+                .await?
+                .context("A player unlinked their bonk.io account.")?,
+        );
+    }
+    let password = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(PASSWORD_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+
+    //Players can join under any of their names.
+    let mut whitelist = vec![];
+    for player in players {
+        whitelist.extend(players::linked_names(db, player).await?);
+    }
+
+    let mut room_parameters: RoomParameters = toml::de::from_str(room)?;
+    room_parameters.name = names.join(" vs ");
+    room_parameters.queue = Queue::Singles;
+    room_parameters.leaderboard = Some(lb.to_string());
+    room_parameters.password = password.clone();
+    room_parameters.whitelist = whitelist;
+    room_parameters.challenge = Some(challenge);
+
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not running.")?;
+    let link = bonk_bot.open_room(ctx, room_parameters).await?;
+
+    for (player, opponent) in players.iter().zip(names.iter().rev()) {
+        let _ = UserId::new(*player)
+            .direct_message(
+                &ctx.http,
+                CreateMessage::new().content(format!(
+                    "Your challenge against {} is ready!\n{}\nPassword: {}",
+                    opponent, link, password
+                )),
+            )
+            .await;
+    }
+
+    Ok(())
+}
+
+async fn linked_name(db: &Pool<Postgres>, discord_id: u64) -> Result<Option<String>> {
+    Ok(
+        sqlx::query_scalar("SELECT name FROM players WHERE discord_id = $1")
+            .bind(discord_id as i64)
+            .fetch_optional(db)
+            .await?,
+    )
+}
@@ -1,9 +1,12 @@
 mod admin_commands;
+pub mod player_commands;
 
-use anyhow::Result;
+use crate::{matchmaking, players, tournament};
+
+use anyhow::{anyhow, Context, Result};
 use serenity::all::{
-    CommandInteraction, CreateInteractionResponse, CreateInteractionResponseMessage,
-    EditInteractionResponse,
+    CommandInteraction, ComponentInteraction, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
 };
 
 pub async fn help(ctx: &serenity::all::Context, interaction: &CommandInteraction) -> Result<()> {
@@ -33,7 +36,17 @@ pub async fn help(ctx: &serenity::all::Context, interaction: &CommandInteraction
                 concat!(
                     "__Commands:__\n",
                     "**help, h:** The help menu that you're currently reading.\n",
-                    "**ping:** Pong!",
+                    "**ping:** Pong!\n",
+                    "**link:** Links your Discord account to your bonk.io account.\n",
+                    "**rating, r <leaderboard abbreviation> [name]:** Shows a player's rating.\n",
+                    "**profile <leaderboard abbreviation> [name]:** Shows a player's rating, record and recent matches.\n",
+                    "**history [name] [leaderboard abbreviation]:** Lists a player's rated games.\n",
+                    "**h2h <name>, [name] [leaderboard abbreviation]:** Compares two players who played against each other.\n",
+                    "**graph [name] [leaderboard abbreviation]:** Draws a player's rating over time.\n",
+                    "**mapstats [leaderboard abbreviation]:** Shows play, strike and win stats for each map.\n",
+                    "**challenge [leaderboard abbreviation] [time]:** Challenges the player in \"user:\" to a match in a bot room.\n",
+                    "**tournament <join/leave/list/bracket> [tournament]:** Signs you up for a tournament or shows its bracket.\n",
+                    "**queue, q <join/leave/list> [leaderboard abbreviation]:** Pairs you with a player of similar rating and opens a room for one game.",
                 )
                 .to_string()
                     + if admin {
@@ -71,6 +84,176 @@ pub async fn ping(
     Ok(())
 }
 
+pub async fn link(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Gives you a code to type in a room hosted by this bot. ",
+            "Typing it in the room links your Discord account to the bonk.io account that typed it.",
+            "\n\nUsage: link, link remove",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<super::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let response = match args.first() {
+        Some(&"remove") | Some(&"r") => {
+            match players::unlink(&db.db, interaction.user.id.get()).await? {
+                Some(name) => format!("Your Discord account is no longer linked to {}.", name),
+                None => "Your Discord account isn't linked.".to_string(),
+            }
+        }
+        Some(_) => return Err(anyhow!("Invalid argument.")),
+        None => {
+            let code = players::create_link_code(&db.db, interaction.user.id.get()).await?;
+            format!(
+                "Type \"!link {}\" in a room hosted by this bot within {} minutes.",
+                code,
+                players::LINK_CODE_MINUTES
+            )
+        }
+    };
+
+    interaction
+        .create_response(&ctx.http, response_message(response))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn tournament(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Signs you up for a tournament or shows tournaments and their brackets. ",
+            "Link your bonk.io account with \"link\" before joining.",
+            "\n\nUsage: tournament join <tournament>, tournament leave <tournament>, ",
+            "tournament list, tournament bracket <tournament>",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<super::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let name = args.get(1).copied().context("Missing tournament name.");
+    let response = match args.first() {
+        Some(&"join") | Some(&"j") => {
+            tournament::join(&db.db, name?, interaction.user.id.get()).await?
+        }
+        Some(&"leave") | Some(&"l") => {
+            tournament::leave(&db.db, name?, interaction.user.id.get()).await?
+        }
+        Some(&"bracket") | Some(&"b") => tournament::show(&db.db, name?).await?,
+        Some(&"list") | Some(&"ls") | None => tournament::list(&db.db).await?,
+        Some(_) => return Err(anyhow!("Invalid argument.")),
+    };
+
+    interaction
+        .create_response(&ctx.http, response_message(response))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn queue(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Finds you a rated singles match on a leaderboard. You're paired with the closest ",
+            "rated player in the queue, and the range of ratings you can be paired with widens ",
+            "the longer you wait. The room link is posted in this channel and the room closes ",
+            "after one game. Link your bonk.io account with \"link\" before joining.",
+            "\n\nUsage: queue join <leaderboard abbreviation>, queue leave, queue list",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<super::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let user = interaction.user.id.get();
+    let response = match args.first() {
+        Some(&"join") | Some(&"j") => {
+            let lb = args
+                .get(1)
+                .copied()
+                .context("Missing leaderboard abbreviation.")?;
+            let players = matchmaking::join(ctx, &db.db, lb, user, interaction.channel_id).await?;
+            format!(
+                "You joined the {} queue. {} player{} waiting.",
+                lb,
+                players,
+                if players == 1 { " is" } else { "s are" }
+            )
+        }
+        Some(&"leave") | Some(&"l") => match matchmaking::leave(ctx, user).await? {
+            Some(lb) => format!("You left the {} queue.", lb),
+            None => return Err(anyhow!("You aren't in a queue.")),
+        },
+        Some(&"list") | Some(&"ls") | None => {
+            let queues = matchmaking::list(ctx).await?;
+            if queues.is_empty() {
+                "Queues:\n\nNobody is queued.".to_string()
+            } else {
+                format!(
+                    "Queues:\n\n{}",
+                    queues
+                        .iter()
+                        .map(|(lb, players)| format!("{}: {} waiting", lb, players))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )
+            }
+        }
+        Some(_) => return Err(anyhow!("Invalid argument.")),
+    };
+
+    interaction
+        .create_response(&ctx.http, response_message(response))
+        .await?;
+
+    Ok(())
+}
+
 pub async fn a(
     ctx: &serenity::all::Context,
     interaction: &CommandInteraction,
@@ -103,6 +286,12 @@ pub async fn a(
                     "leaderboard" | "lb" => {
                         admin_commands::leaderboard(ctx, interaction, args).await?
                     }
+                    "player" | "p" => admin_commands::player(ctx, interaction, args).await?,
+                    "match" | "m" => admin_commands::match_result(ctx, interaction, args).await?,
+                    "maps" => admin_commands::maps(ctx, interaction, args).await?,
+                    "tournament" | "t" => {
+                        admin_commands::tournament(ctx, interaction, args).await?
+                    }
                     "roomlog" => admin_commands::roomlog(ctx, interaction, args).await?,
                     "open" | "o" => admin_commands::open(ctx, interaction, args).await?,
                     "shutdown" | "sd" => admin_commands::shutdown(ctx, interaction, args).await?,
@@ -161,6 +350,25 @@ pub async fn help_check(
     Ok(false)
 }
 
+///Handles message components like buttons. Custom ids start with the name of the command
+///that created the component followed by a colon.
+pub async fn component(
+    ctx: &serenity::all::Context,
+    interaction: &ComponentInteraction,
+) -> Result<()> {
+    let custom_id = interaction.data.custom_id.as_str();
+    let (command, args) = custom_id.split_once(':').unwrap_or((custom_id, ""));
+
+    match command {
+        "history" => player_commands::history_button(ctx, interaction, args).await?,
+        "leaderboard" => player_commands::leaderboard_select(ctx, interaction, args).await?,
+        "challenge" => player_commands::challenge_button(ctx, interaction, args).await?,
+        _ => return Err(anyhow!("Unknown component.")),
+    }
+
+    Ok(())
+}
+
 pub fn response_message(message: impl Into<String>) -> CreateInteractionResponse {
     let message = CreateInteractionResponseMessage::new()
         .content(message)
@@ -169,6 +377,12 @@ pub fn response_message(message: impl Into<String>) -> CreateInteractionResponse
     CreateInteractionResponse::Message(message)
 }
 
+pub fn embed_message(embed: CreateEmbed) -> CreateInteractionResponse {
+    let message = CreateInteractionResponseMessage::new().embed(embed);
+
+    CreateInteractionResponse::Message(message)
+}
+
 pub fn loading_message() -> CreateInteractionResponse {
     let message = CreateInteractionResponseMessage::new().ephemeral(true);
 
@@ -1,15 +1,23 @@
 use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
-use serenity::all::{ChannelId, CommandDataOptionValue, CommandInteraction, CreateMessage};
-use tokio::time;
+use serde::Deserialize;
+use serenity::all::{
+    ChannelId, CommandDataOptionValue, CommandInteraction, CreateAttachment,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+};
+use sqlx::types::time::Date;
+use tokio::{sync::oneshot, time};
 
 use crate::bonk_bot::{room_maker::RoomParameters, BonkBotKey};
-use crate::DatabaseValue;
+use crate::tournament::{self, bracket::Format};
+use crate::{maps, players, DatabaseValue};
 
-use super::super::leaderboard::LeaderboardSettings;
+use super::super::leaderboard::{LeaderboardMessage, LeaderboardSettings, NewGame};
 use super::{edit_message, help_check, loading_message, response_message};
 
+const SEASON_INFO_PLACEMENTS: i64 = 20;
+
 pub async fn admin_help(
     ctx: &serenity::all::Context,
     interaction: &CommandInteraction,
@@ -22,8 +30,15 @@ pub async fn admin_help(
                 "__Commands:__\n",
                 "**admins <add/remove/list>:** Edits the list of admins who have access to the \"a\" command.\n",
                 "**leaderboard, lb <create/remove/list>:** Creates a leaderboard from a config file and a specified Discord channel.\n",
-                "**leaderboard, lb edit <leaderboard abbreviation>:** Modifies the leaderboard config file and channel. May break the leaderboard.\n",
+                "**leaderboard, lb edit <leaderboard abbreviation>:** Modifies the leaderboard config file and channel. Use \"lb recalculate\" to apply the new settings to past games.\n",
+                "**leaderboard, lb season <start/list/info> <leaderboard abbreviation>:** Starts a new season with a hard or soft reset and shows past seasons.\n",
+                "**leaderboard, lb recalculate, recalc <leaderboard abbreviation>:** Replays every game of a leaderboard under its current settings.\n",
                 "**leaderboard, lb match_channel <get/set/clear> <leaderboard abbreviation>:** Sets the channel where matches are posted.\n",
+                "**leaderboard, lb room <get/set/clear> <leaderboard abbreviation>:** Sets the room config used for challenge and matchmaking rooms.\n",
+                "**player, p <info/alias/unalias/rename/merge>:** Edits players and the bonk names that belong to them.\n",
+                "**match, m <void/add>:** Removes a rated game or adds a game from a file.\n",
+                "**maps <add/remove/list/pool>:** Edits the map library and map pools for room configs.\n",
+                "**tournament, t <create/start/report/delete>:** Runs tournaments with rooms for every bracket match.\n",
                 "**roomlog <get/set/clear>:** Edits the room log channel where room links are posted.\n",
                 "**open, o:** Creates a room from a room config file!\n",
                 "**closeall, ca:** Closes all rooms.\n",
@@ -327,6 +342,40 @@ pub async fn leaderboard(
                 args.remove(0);
                 match_channel(ctx, db, interaction, args).await?;
             }
+            "room" => {
+                let mut args = args.clone();
+                args.remove(0);
+                room_template(ctx, db, interaction, args).await?;
+            }
+            "season" => {
+                let mut args = args.clone();
+                args.remove(0);
+                season(ctx, db, interaction, args).await?;
+            }
+            "recalculate" | "recalc" => {
+                let lb_abbr = *args.get(1).context("Missing leaderboard abbreviation.")?;
+
+                interaction
+                    .create_response(&ctx.http, loading_message())
+                    .await?;
+
+                let bonk_bot = {
+                    let data = ctx.data.read().await;
+                    data.get::<BonkBotKey>().cloned()
+                }
+                .context("Bonk bot not found.")?;
+                let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, lb_abbr).await?;
+
+                let (str, str_rx) = oneshot::channel();
+                leaderboard_tx
+                    .send(LeaderboardMessage::Recalculate { str })
+                    .await?;
+                let response = str_rx.await??;
+
+                interaction
+                    .edit_response(&ctx.http, edit_message(response))
+                    .await?;
+            }
             _ => {
                 return Err(anyhow!("Invalid argument."));
             }
@@ -429,6 +478,741 @@ pub async fn match_channel(
     Ok(())
 }
 
+///Edits the room config that challenge and matchmaking rooms on a leaderboard are made from.
+pub async fn room_template(
+    ctx: &serenity::all::Context,
+    db: DatabaseValue,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command edits the room config used for a leaderboard's challenge and ",
+            "matchmaking rooms. \"set\" takes the config as an attachment. The room name, ",
+            "password, queue and leaderboard are filled in for each match.\n\n",
+            "Usage: lb room <get/set/clear> <leaderboard abbreviation>",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let option = *args
+        .first()
+        .context("Missing get/set/clear argument from \"a leaderboard room\" command.")?;
+    let abbreviation = *args.get(1).context("Missing leaderboard abbreviation.")?;
+
+    match option {
+        "get" | "g" => {
+            let room: Option<String> =
+                sqlx::query_scalar("SELECT room FROM leaderboard WHERE abbreviation = $1")
+                    .bind(abbreviation)
+                    .fetch_optional(db.db.as_ref())
+                    .await?
+                    .context("Leaderboard not found.")?;
+            let room = room.context("This leaderboard doesn't have a room config.")?;
+
+            interaction
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .add_file(CreateAttachment::bytes(room.into_bytes(), "room.toml"))
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+        }
+        "set" | "s" => {
+            let attachment = interaction
+                .data
+                .resolved
+                .attachments
+                .values()
+                .next()
+                .context("Attachment not found.")?;
+            let room = reqwest::get(&attachment.url).await?.text().await?;
+            toml::de::from_str::<RoomParameters>(&room).context("Invalid room config.")?;
+
+            let updated = sqlx::query("UPDATE leaderboard SET room = $1 WHERE abbreviation = $2")
+                .bind(room)
+                .bind(abbreviation)
+                .execute(db.db.as_ref())
+                .await?;
+            if updated.rows_affected() == 0 {
+                return Err(anyhow!("Leaderboard not found."));
+            }
+
+            interaction
+                .create_response(&ctx.http, response_message("Room config set."))
+                .await?;
+        }
+        "clear" | "c" => {
+            sqlx::query("UPDATE leaderboard SET room = NULL WHERE abbreviation = $1")
+                .bind(abbreviation)
+                .execute(db.db.as_ref())
+                .await?;
+
+            interaction
+                .create_response(&ctx.http, response_message("Room config cleared."))
+                .await?;
+        }
+        _ => return Err(anyhow!("Invalid argument.")),
+    }
+
+    Ok(())
+}
+
+pub async fn season(
+    ctx: &serenity::all::Context,
+    db: DatabaseValue,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command manages a leaderboard's seasons. \"start <abbreviation> <hard/soft>\" ",
+            "archives the current standings and starts a new season. \"info\" takes an optional ",
+            "season number and defaults to the current season.",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    if let Some(&option) = args.first() {
+        let abbreviation = *args.get(1).context("Missing leaderboard abbreviation.")?;
+
+        match option {
+            "start" | "s" => {
+                let hard_reset = match args.get(2) {
+                    Some(&"hard") => true,
+                    Some(&"soft") => false,
+                    _ => return Err(anyhow!("Missing hard/soft argument.")),
+                };
+
+                interaction
+                    .create_response(&ctx.http, loading_message())
+                    .await?;
+
+                let bonk_bot = {
+                    let data = ctx.data.read().await;
+                    data.get::<BonkBotKey>().cloned()
+                }
+                .context("Bonk bot not found.")?;
+                let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, abbreviation).await?;
+
+                let (str, str_rx) = oneshot::channel();
+                leaderboard_tx
+                    .send(LeaderboardMessage::NewSeason { hard_reset, str })
+                    .await?;
+                let response = str_rx.await??;
+
+                interaction
+                    .edit_response(&ctx.http, edit_message(response))
+                    .await?;
+            }
+            "list" | "l" | "ls" => {
+                let seasons: Vec<(i32, Date, bool, i64)> = sqlx::query_as(
+                    "SELECT s.season_num, s.start, s.hard_reset, COUNT(g.id) FROM lb_seasons s \
+                    JOIN leaderboard l ON l.id = s.lb_id \
+                    LEFT JOIN lb_games g ON g.lb_id = s.lb_id AND g.season_num = s.season_num \
+                    WHERE l.abbreviation = $1 \
+                    GROUP BY s.season_num, s.start, s.hard_reset \
+                    ORDER BY s.season_num",
+                )
+                .bind(abbreviation)
+                .fetch_all(db.db.as_ref())
+                .await?;
+
+                let list = seasons
+                    .iter()
+                    .map(|(season_num, start, hard_reset, games)| {
+                        format!(
+                            "Season {}: started {} with a {} reset, {} game{}.",
+                            season_num,
+                            start,
+                            if *hard_reset { "hard" } else { "soft" },
+                            games,
+                            if *games == 1 { "" } else { "s" },
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                interaction
+                    .create_response(
+                        &ctx.http,
+                        response_message(format!("{} seasons:\n\n{}", abbreviation, list)),
+                    )
+                    .await?;
+            }
+            "info" | "i" => {
+                let lb_id: i64 =
+                    sqlx::query_scalar("SELECT id FROM leaderboard WHERE abbreviation = $1")
+                        .bind(abbreviation)
+                        .fetch_optional(db.db.as_ref())
+                        .await?
+                        .context("Leaderboard not found.")?;
+
+                let season_num: i32 = match args.get(2) {
+                    Some(season_num) => season_num.parse()?,
+                    None => sqlx::query_scalar::<_, Option<i32>>(
+                        "SELECT MAX(season_num) FROM lb_seasons WHERE lb_id = $1",
+                    )
+                    .bind(lb_id)
+                    .fetch_one(db.db.as_ref())
+                    .await?
+                    .context("This leaderboard doesn't have any seasons.")?,
+                };
+
+                let (start, hard_reset): (Date, bool) = sqlx::query_as(
+                    "SELECT start, hard_reset FROM lb_seasons WHERE lb_id = $1 AND season_num = $2",
+                )
+                .bind(lb_id)
+                .bind(season_num)
+                .fetch_optional(db.db.as_ref())
+                .await?
+                .context("Season not found.")?;
+                let games: i64 = sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM lb_games WHERE lb_id = $1 AND season_num = $2",
+                )
+                .bind(lb_id)
+                .bind(season_num)
+                .fetch_one(db.db.as_ref())
+                .await?;
+                let standings: Vec<(i32, String, f64, f64)> = sqlx::query_as(
+                    "SELECT placement, name, rating, rating_deviation FROM lb_season_standings \
+                    WHERE lb_id = $1 AND season_num = $2 ORDER BY placement LIMIT $3",
+                )
+                .bind(lb_id)
+                .bind(season_num)
+                .bind(SEASON_INFO_PLACEMENTS)
+                .fetch_all(db.db.as_ref())
+                .await?;
+
+                let mut output = format!(
+                    "Season {}: started {} with a {} reset, {} game{}.",
+                    season_num,
+                    start,
+                    if hard_reset { "hard" } else { "soft" },
+                    games,
+                    if games == 1 { "" } else { "s" },
+                );
+                if standings.is_empty() {
+                    output += "\nThis season is in progress.";
+                } else {
+                    output += "\n\nFinal standings:";
+                    for (placement, name, rating, rating_deviation) in standings {
+                        output += &format!(
+                            "\n{}. {} ({:.0}, σ = {:.0})",
+                            placement, name, rating, rating_deviation
+                        );
+                    }
+                }
+
+                interaction
+                    .create_response(&ctx.http, response_message(output))
+                    .await?;
+            }
+            _ => return Err(anyhow!("Invalid argument.")),
+        }
+    } else {
+        return Err(anyhow!(
+            "Missing start/list/info argument from \"a leaderboard season\" command."
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn player(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command edits players and the bonk names that belong to them. ",
+            "Separate names with commas.\n\n",
+            "Usage:\n",
+            "player info <name>\n",
+            "player alias <name>, <new bonk name>\n",
+            "player unalias <bonk name>\n",
+            "player rename <name>, <new display name>\n",
+            "player merge <name>, <name to merge into the first player>",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .ok_or(anyhow!("Failed to connect to database."))?;
+
+    let option = *args
+        .first()
+        .context("Missing argument for \"a player\" command.")?;
+    let names: Vec<String> = args[1..]
+        .join(" ")
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    let name = names.first().context("Missing player name.")?;
+
+    match option {
+        "info" | "i" => {
+            let response = players::info(&db.db, name).await?;
+
+            interaction
+                .create_response(&ctx.http, response_message(response))
+                .await?;
+        }
+        "alias" | "a" => {
+            let alias = names.get(1).context("Missing new bonk name.")?;
+            let response = players::add_alias(&db.db, name, alias).await?;
+
+            interaction
+                .create_response(&ctx.http, response_message(response))
+                .await?;
+        }
+        "unalias" | "ua" => {
+            let response = players::remove_alias(&db.db, name).await?;
+
+            interaction
+                .create_response(&ctx.http, response_message(response))
+                .await?;
+        }
+        "rename" => {
+            let new_name = names.get(1).context("Missing new display name.")?;
+            let response = players::rename(&db.db, name, new_name).await?;
+
+            interaction
+                .create_response(&ctx.http, response_message(response))
+                .await?;
+        }
+        "merge" => {
+            let other = names
+                .get(1)
+                .context("Missing the name of the second player.")?;
+
+            interaction
+                .create_response(&ctx.http, loading_message())
+                .await?;
+
+            let (mut response, leaderboards) = players::merge(&db.db, name, other).await?;
+
+            let bonk_bot = {
+                let data = ctx.data.read().await;
+                data.get::<BonkBotKey>().cloned()
+            }
+            .context("Bonk bot not found.")?;
+            for abbreviation in leaderboards {
+                let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, &abbreviation).await?;
+
+                let (str, str_rx) = oneshot::channel();
+                leaderboard_tx
+                    .send(LeaderboardMessage::Recalculate { str })
+                    .await?;
+                response += &format!("\n{}: {}", abbreviation, str_rx.await??);
+            }
+
+            interaction
+                .edit_response(&ctx.http, edit_message(response))
+                .await?;
+        }
+        _ => return Err(anyhow!("Invalid argument.")),
+    }
+
+    Ok(())
+}
+
+///A game played outside of the bot for "match add".
+#[derive(Deserialize)]
+struct MatchFile {
+    teams: Vec<Vec<String>>,
+    ties: Option<Vec<bool>>,
+    score: Option<Vec<f64>>,
+}
+
+pub async fn match_result(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command removes rated games or adds games played outside of the bot. ",
+            "Game ids are shown under each match post and in the history command. ",
+            "Added games are read from an attached file like this:\n\n",
+            "```toml\n",
+            "# Teams in order of placement.\n",
+            "teams = [[\"Player 1\", \"Player 2\"], [\"Player 3\", \"Player 4\"]]\n",
+            "# Optional. Whether each team tied with the next team.\n",
+            "ties = [false]\n",
+            "# Optional. The round score of each team.\n",
+            "score = [5, 3]\n",
+            "```\n",
+            "Usage:\n",
+            "match void <game id>\n",
+            "match add <leaderboard abbreviation>",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .ok_or(anyhow!("Failed to connect to database."))?;
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not found.")?;
+
+    let option = *args
+        .first()
+        .context("Missing argument for \"a match\" command.")?;
+
+    match option {
+        "void" | "v" => {
+            let game_id: i64 = args
+                .get(1)
+                .context("Missing game id.")?
+                .trim_start_matches('#')
+                .parse()
+                .context("Invalid game id.")?;
+            let abbreviation: String = sqlx::query_scalar(
+                "SELECT l.abbreviation FROM lb_games g JOIN leaderboard l ON l.id = g.lb_id \
+                WHERE g.id = $1",
+            )
+            .bind(game_id)
+            .fetch_optional(db.db.as_ref())
+            .await?
+            .context("Unable to find game.")?;
+
+            interaction
+                .create_response(&ctx.http, loading_message())
+                .await?;
+
+            let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, &abbreviation).await?;
+            let (str, str_rx) = oneshot::channel();
+            leaderboard_tx
+                .send(LeaderboardMessage::Void { game_id, str })
+                .await?;
+            let response = str_rx.await??;
+
+            interaction
+                .edit_response(&ctx.http, edit_message(response))
+                .await?;
+        }
+        "add" | "a" => {
+            let abbreviation = *args.get(1).context("Missing leaderboard abbreviation.")?;
+            let attachment = &interaction
+                .data
+                .resolved
+                .attachments
+                .values()
+                .next()
+                .context("Attachment not found.")?;
+
+            let response = reqwest::get(&attachment.url).await?;
+            let file = response.text().await?;
+            let game: MatchFile = toml::de::from_str(&file)?;
+            let ties = game
+                .ties
+                .unwrap_or(vec![false; game.teams.len().saturating_sub(1)]);
+
+            if game.teams.len() < 2 || game.teams.iter().any(|team| team.is_empty()) {
+                return Err(anyhow!("A game needs at least two teams with players."));
+            }
+            if ties.len() != game.teams.len() - 1 {
+                return Err(anyhow!(
+                    "There should be one tie for every team but the last."
+                ));
+            }
+            if game
+                .score
+                .as_ref()
+                .is_some_and(|score| score.len() != game.teams.len())
+            {
+                return Err(anyhow!("There should be one score for every team."));
+            }
+            if game
+                .score
+                .iter()
+                .flatten()
+                .any(|score| !score.is_finite() || *score < 0.)
+            {
+                return Err(anyhow!("Scores can't be negative."));
+            }
+            let mut names: Vec<&String> = game.teams.iter().flatten().collect();
+            names.sort();
+            names.dedup();
+            if names.len() != game.teams.iter().flatten().count() {
+                return Err(anyhow!("A player can't be in a game more than once."));
+            }
+
+            interaction
+                .create_response(&ctx.http, loading_message())
+                .await?;
+
+            let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, abbreviation).await?;
+            let (match_str, match_str_rx) = oneshot::channel();
+            leaderboard_tx
+                .send(LeaderboardMessage::Update {
+                    game: NewGame {
+                        teams: game.teams,
+                        ties,
+                        score: game.score,
+                        rounds: vec![],
+                        map: None,
+                        struck_maps: vec![],
+                        sides: vec![],
+                    },
+                    match_str,
+                })
+                .await?;
+            let response = match_str_rx.await??;
+
+            interaction
+                .edit_response(&ctx.http, edit_message(format!("Added game: {}", response)))
+                .await?;
+        }
+        _ => return Err(anyhow!("Invalid argument.")),
+    }
+
+    Ok(())
+}
+
+pub async fn maps(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command edits the map library and the map pools that room configs can use ",
+            "with map_pool. Maps are added from an attached file with the raw data of one map ",
+            "or a list of maps, like the output of sgrAPI.getFav(0);.\n\n",
+            "Usage:\n",
+            "maps add [pool]\n",
+            "maps remove <map id>\n",
+            "maps list [pool]\n",
+            "maps pool <create/delete> <pool>\n",
+            "maps pool <add/remove> <pool> <map id> [map id...]\n",
+            "maps pool list",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .ok_or(anyhow!("Failed to connect to database."))?;
+
+    let map_ids = |args: &[&str]| -> Result<Vec<i64>> {
+        if args.is_empty() {
+            return Err(anyhow!("Missing map id."));
+        }
+        args.iter()
+            .map(|id| {
+                id.trim_start_matches('#')
+                    .parse()
+                    .context("Invalid map id.")
+            })
+            .collect()
+    };
+
+    let option = *args
+        .first()
+        .context("Missing argument for \"a maps\" command.")?;
+    let response = match option {
+        "add" | "a" => {
+            let attachment = &interaction
+                .data
+                .resolved
+                .attachments
+                .values()
+                .next()
+                .context("Attachment not found.")?;
+            let file = reqwest::get(&attachment.url).await?.text().await?;
+
+            maps::add(&db.db, &file, args.get(1).copied()).await?
+        }
+        "remove" | "r" => maps::remove(&db.db, map_ids(&args[1..])?[0]).await?,
+        "list" | "l" | "ls" => maps::list(&db.db, args.get(1).copied()).await?,
+        "pool" | "p" => {
+            let pool_option = *args.get(1).context("Missing argument for \"maps pool\".")?;
+            if let "list" | "l" | "ls" = pool_option {
+                maps::list_pools(&db.db).await?
+            } else {
+                let pool = *args.get(2).context("Missing map pool name.")?;
+                match pool_option {
+                    "create" | "c" => maps::create_pool(&db.db, pool).await?,
+                    "delete" | "d" => maps::delete_pool(&db.db, pool).await?,
+                    "add" | "a" => maps::add_pool_maps(&db.db, pool, &map_ids(&args[3..])?).await?,
+                    "remove" | "r" => {
+                        maps::remove_pool_maps(&db.db, pool, &map_ids(&args[3..])?).await?
+                    }
+                    _ => return Err(anyhow!("Invalid argument.")),
+                }
+            }
+        }
+        _ => return Err(anyhow!("Invalid argument.")),
+    };
+
+    interaction
+        .create_response(&ctx.http, response_message(response))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn tournament(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "This command runs tournaments. Create makes a tournament from a room config file ",
+            "attachment and posts the bracket in the channel from the \"channel:\" option. ",
+            "Players sign up with \"tournament join\" and are seeded by rating when it starts. ",
+            "Every bracket match gets a room only its two players can join, and the result of ",
+            "the rated game moves the bracket along. Use report to enter a result by hand.\n\n",
+            "Usage:\n",
+            "tournament create <tournament> <leaderboard abbreviation> <single/double/swiss> [swiss rounds]\n",
+            "tournament start <tournament>\n",
+            "tournament report <tournament> <match number> <winner>\n",
+            "tournament delete <tournament>",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .ok_or(anyhow!("Failed to connect to database."))?;
+
+    let option = *args
+        .first()
+        .context("Missing argument for \"a tournament\" command.")?;
+    let name = *args.get(1).context("Missing tournament name.")?;
+    match option {
+        "create" | "c" => {
+            let lb = *args.get(2).context("Missing leaderboard abbreviation.")?;
+            let format = args
+                .get(3)
+                .and_then(|format| Format::parse(format))
+                .context("The format must be single, double or swiss.")?;
+            let rounds = match args.get(4) {
+                Some(rounds) => Some(rounds.parse().context("Invalid number of rounds.")?),
+                None => None,
+            };
+
+            let channel = &interaction
+                .data
+                .options
+                .iter()
+                .find(|o| o.name == "channel")
+                .context("Channel not selected.")?
+                .value;
+            let CommandDataOptionValue::Channel(channel) = channel else {
+                return Err(anyhow!("Channel not selected."));
+            };
+            let attachment = interaction
+                .data
+                .resolved
+                .attachments
+                .values()
+                .next()
+                .context("Attachment not found.")?;
+            let room = reqwest::get(&attachment.url).await?.text().await?;
+
+            let message =
+                tournament::create(&db.db, name, lb, format, rounds, channel.get(), &room).await?;
+            channel.say(&ctx.http, &message).await?;
+            interaction
+                .create_response(&ctx.http, response_message(message))
+                .await?;
+        }
+        "start" | "s" => {
+            interaction
+                .create_response(&ctx.http, loading_message())
+                .await?;
+            tournament::start(ctx, name).await?;
+            interaction
+                .edit_response(&ctx.http, edit_message(format!("{} has started!", name)))
+                .await?;
+        }
+        "report" | "r" => {
+            let match_number = args
+                .get(2)
+                .and_then(|number| number.trim_start_matches('#').parse().ok())
+                .context("Invalid match number.")?;
+            let winner = args.get(3..).unwrap_or_default().join(" ");
+
+            interaction
+                .create_response(&ctx.http, loading_message())
+                .await?;
+            tournament::report_by_name(ctx, name, match_number, &winner).await?;
+            interaction
+                .edit_response(
+                    &ctx.http,
+                    edit_message(format!("Reported match #{}.", match_number)),
+                )
+                .await?;
+        }
+        "delete" | "d" => {
+            let message = tournament::delete(&db.db, name).await?;
+            interaction
+                .create_response(&ctx.http, response_message(message))
+                .await?;
+        }
+        _ => return Err(anyhow!("Invalid argument.")),
+    }
+
+    Ok(())
+}
+
 pub async fn roomlog(
     ctx: &serenity::all::Context,
     interaction: &CommandInteraction,
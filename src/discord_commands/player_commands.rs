@@ -0,0 +1,867 @@
+use anyhow::{anyhow, Context, Result};
+use serenity::all::{
+    ButtonStyle, CommandDataOptionValue, CommandInteraction, ComponentInteraction,
+    ComponentInteractionDataKind, CreateActionRow, CreateAttachment, CreateButton, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateThread,
+    MessageFlags,
+};
+use sqlx::{Pool, Postgres};
+use tokio::sync::oneshot;
+
+use crate::bonk_bot::BonkBotKey;
+use crate::challenges;
+use crate::leaderboard::{
+    escaped, graph, leaderboard_page,
+    stats::{sparkline, GameResult, HeadToHead, PlayerProfile, PlayerQuery},
+    LeaderboardMessage,
+};
+
+use super::{embed_message, help_check};
+
+const RECENT_GAMES: usize = 5;
+const TREND_GAMES: usize = 20;
+const HISTORY_PAGE_GAMES: usize = 10;
+const MAX_EMBED_FIELDS: usize = 25;
+
+pub async fn rating(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Shows a player's rating on a leaderboard. Shows your linked account if no name ",
+            "or \"user:\" is given.\n\nUsage: rating <leaderboard abbreviation> [name]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let profile = player_profile(ctx, interaction, &args).await?;
+
+    interaction
+        .create_response(&ctx.http, embed_message(rating_embed(&profile)))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn profile(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Shows a player's rating, record, recent matches and rating trend on a leaderboard. ",
+            "Shows your linked account if no name or \"user:\" is given.",
+            "\n\nUsage: profile <leaderboard abbreviation> [name]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let profile = player_profile(ctx, interaction, &args).await?;
+
+    let recent = profile
+        .games
+        .iter()
+        .rev()
+        .take(RECENT_GAMES)
+        .map(|game| {
+            format!(
+                "{} vs {} ({:.0}, {:+.0})",
+                game.result.letter(),
+                game.opponents(&profile.names)
+                    .iter()
+                    .map(escaped)
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                game.new_rating,
+                game.new_rating.round() - game.old_rating.round(),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let trend_games = &profile.games[profile.games.len().saturating_sub(TREND_GAMES)..];
+    let trend = match (trend_games.first(), trend_games.last()) {
+        (Some(first), Some(last)) if trend_games.len() > 1 => {
+            let ratings: Vec<f64> = trend_games.iter().map(|game| game.new_rating).collect();
+            format!(
+                "{}\n{:+.0} over the last {} games",
+                sparkline(&ratings),
+                last.new_rating.round() - first.old_rating.round(),
+                trend_games.len()
+            )
+        }
+        _ => "Not enough games.".to_string(),
+    };
+
+    let embed = rating_embed(&profile)
+        .field(
+            "Record",
+            format!(
+                "{}W {}L {}D",
+                profile.count(GameResult::Win),
+                profile.count(GameResult::Loss),
+                profile.count(GameResult::Draw)
+            ),
+            true,
+        )
+        .field(
+            "Recent matches",
+            if recent.is_empty() {
+                "None".to_string()
+            } else {
+                recent
+            },
+            false,
+        )
+        .field("Rating trend", trend, false);
+
+    interaction
+        .create_response(&ctx.http, embed_message(embed))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn history(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Lists a player's rated games. The leaderboard defaults to the one the player ",
+            "played on most recently. Shows your linked account if no name or \"user:\" is given.",
+            "\n\nUsage: history [name] [leaderboard abbreviation]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let (name, abbreviation) = split_leaderboard(&db.db, &args).await?;
+    let query = player_query(interaction, &name);
+
+    let abbreviation = match abbreviation {
+        Some(abbreviation) => abbreviation,
+        None => latest_leaderboard(&db.db, &query)
+            .await?
+            .context("This player hasn't played on any leaderboards.")?,
+    };
+
+    let profile = fetch_profile(ctx, &abbreviation, query).await?;
+    let (embed, components) = history_page(&profile, &abbreviation, 0);
+
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .components(components),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn graph(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Draws a player's rating over time with a band of one rating deviation and lines ",
+            "at the start of each season. The leaderboard defaults to the one the player ",
+            "played on most recently. Shows your linked account if no name or \"user:\" is given.",
+            "\n\nUsage: graph [name] [leaderboard abbreviation]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let (name, abbreviation) = split_leaderboard(&db.db, &args).await?;
+    let query = player_query(interaction, &name);
+
+    let abbreviation = match abbreviation {
+        Some(abbreviation) => abbreviation,
+        None => latest_leaderboard(&db.db, &query)
+            .await?
+            .context("This player hasn't played on any leaderboards.")?,
+    };
+
+    let profile = fetch_profile(ctx, &abbreviation, query).await?;
+    let png = graph::rating_graph(&profile)?;
+
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(rating_embed(&profile).image("attachment://graph.png"))
+                    .add_file(CreateAttachment::bytes(png, "graph.png")),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn mapstats(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Shows how often each map was played and struck and how often each side won on it. ",
+            "Side 1 is the picker in singles, red in football and the first team in teams. ",
+            "Lists the best players on each map if the leaderboard has map ratings. The ",
+            "leaderboard defaults to the one with the most recent game.",
+            "\n\nUsage: mapstats [leaderboard abbreviation]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let abbreviation = match args.first() {
+        Some(abbreviation) => abbreviation.to_string(),
+        None => sqlx::query_scalar(
+            "SELECT l.abbreviation FROM lb_games g JOIN leaderboard l ON l.id = g.lb_id \
+            ORDER BY g.id DESC LIMIT 1",
+        )
+        .fetch_optional(db.db.as_ref())
+        .await?
+        .context("No games have been played.")?,
+    };
+
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not found.")?;
+    let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, &abbreviation).await?;
+    let (stats, stats_rx) = oneshot::channel();
+    leaderboard_tx
+        .send(LeaderboardMessage::MapStats { stats })
+        .await?;
+    let stats = stats_rx.await??;
+
+    let mut embed = CreateEmbed::new().title(format!("Map stats ({})", abbreviation));
+    if stats.is_empty() {
+        embed = embed.description("No maps have been recorded.");
+    }
+    for map in stats.iter().take(MAX_EMBED_FIELDS) {
+        let mut value = format!("Played {} · Struck {}", map.played, map.struck);
+        if map.side_wins.iter().sum::<usize>() > 0 {
+            value += &format!(
+                "\nSide wins: {}",
+                map.side_win_rates()
+                    .iter()
+                    .map(|rate| format!("{:.0}%", rate * 100.))
+                    .collect::<Vec<String>>()
+                    .join(" - ")
+            );
+        }
+        for (i, (name, rating)) in map.top_players.iter().enumerate() {
+            value += &format!("\n{}. {} ({:.0})", i + 1, escaped(name), rating);
+        }
+
+        embed = embed.field(
+            format!("{} by {} (#{})", map.name, map.author, map.id),
+            value,
+            false,
+        );
+    }
+
+    interaction
+        .create_response(&ctx.http, embed_message(embed))
+        .await?;
+
+    Ok(())
+}
+
+///Handles the history page buttons. The custom id is "history:<abbreviation>:<lb player id>:<page>".
+pub async fn history_button(
+    ctx: &serenity::all::Context,
+    interaction: &ComponentInteraction,
+    args: &str,
+) -> Result<()> {
+    let mut args = args.rsplitn(3, ':');
+    let page: usize = args.next().context("Missing page.")?.parse()?;
+    let id: i64 = args.next().context("Missing player.")?.parse()?;
+    let abbreviation = args.next().context("Missing leaderboard.")?;
+
+    let profile = fetch_profile(ctx, abbreviation, PlayerQuery::Id(id)).await?;
+    let (embed, components) = history_page(&profile, abbreviation, page);
+
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .components(components),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+///Handles the leaderboard channel page selector. The custom id is "leaderboard:<abbreviation>".
+///Pages are shown privately so the shared leaderboard message stays on the first page.
+pub async fn leaderboard_select(
+    ctx: &serenity::all::Context,
+    interaction: &ComponentInteraction,
+    abbreviation: &str,
+) -> Result<()> {
+    let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind else {
+        return Err(anyhow!("Expected a page."));
+    };
+    let page: usize = values.first().context("Missing page.")?.parse()?;
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let (id, name): (i64, String) =
+        sqlx::query_as("SELECT id, settings->>'name' FROM leaderboard WHERE abbreviation = $1")
+            .bind(abbreviation)
+            .fetch_optional(db.db.as_ref())
+            .await?
+            .context("Unable to find leaderboard.")?;
+
+    let (embed, components) =
+        leaderboard_page(db.db.as_ref(), id, abbreviation, &name, page).await?;
+    let message = CreateInteractionResponseMessage::new()
+        .embed(embed)
+        .components(components)
+        .ephemeral(true);
+
+    let response = if interaction
+        .message
+        .flags
+        .is_some_and(|flags| flags.contains(MessageFlags::EPHEMERAL))
+    {
+        CreateInteractionResponse::UpdateMessage(message)
+    } else {
+        CreateInteractionResponse::Message(message)
+    };
+    interaction.create_response(&ctx.http, response).await?;
+
+    Ok(())
+}
+
+fn history_page(
+    profile: &PlayerProfile,
+    abbreviation: &str,
+    page: usize,
+) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let pages = profile.games.len().div_ceil(HISTORY_PAGE_GAMES).max(1);
+    let page = page.min(pages - 1);
+
+    let games = profile
+        .games
+        .iter()
+        .rev()
+        .skip(page * HISTORY_PAGE_GAMES)
+        .take(HISTORY_PAGE_GAMES)
+        .map(|game| {
+            format!(
+                "`#{}` {} **{}** vs {}{} ({:.0}, {:+.0})",
+                game.game.id,
+                game.game.day,
+                game.result.letter(),
+                game.opponents(&profile.names)
+                    .iter()
+                    .map(escaped)
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                game.score_string()
+                    .map(|score| format!(", {}", score))
+                    .unwrap_or_default(),
+                game.new_rating,
+                game.new_rating.round() - game.old_rating.round(),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let embed = CreateEmbed::new()
+        .title(format!("{} match history", escaped(&profile.player.name)))
+        .description(if games.is_empty() {
+            "No games played.".to_string()
+        } else {
+            games
+        })
+        .footer(CreateEmbedFooter::new(format!(
+            "{}, page {} of {}",
+            profile.leaderboard,
+            page + 1,
+            pages
+        )));
+
+    let custom_id =
+        |page: usize| format!("history:{}:{}:{}", abbreviation, profile.player.id, page);
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(custom_id(page.saturating_sub(1)))
+            .label("◀")
+            .disabled(page == 0),
+        CreateButton::new(custom_id(page + 1))
+            .label("▶")
+            .disabled(page + 1 >= pages),
+    ]);
+
+    (embed, vec![buttons])
+}
+
+///Challenges another linked player to a match on a leaderboard. The bot opens a room for
+///the match once it's accepted and the time comes.
+pub async fn challenge(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Challenges the player in the \"user:\" option to a match. The leaderboard defaults ",
+            "to the one you played on most recently. The time can be a Discord timestamp or a ",
+            "delay like 30m or 1h30m and defaults to when the challenge is accepted. Both players ",
+            "get the room link and password in their DMs.\n\n",
+            "Usage: challenge [leaderboard abbreviation] [time]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let opponent = interaction
+        .data
+        .options
+        .iter()
+        .find(|o| o.name == "user")
+        .and_then(|o| match o.value {
+            CommandDataOptionValue::User(user) => Some(user),
+            _ => None,
+        })
+        .context("Add the player you're challenging to the \"user:\" option.")?;
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let mut abbreviation = None;
+    let mut time = None;
+    for arg in args.iter().filter(|arg| !arg.is_empty()) {
+        match challenges::parse_time(arg) {
+            Some(parsed) => time = Some(parsed),
+            None => abbreviation = Some(arg.to_string()),
+        }
+    }
+    let abbreviation = match abbreviation {
+        Some(abbreviation) => abbreviation,
+        None => latest_leaderboard(&db.db, &PlayerQuery::Discord(interaction.user.id.get()))
+            .await?
+            .context("You haven't played on any leaderboards. Add a leaderboard abbreviation.")?,
+    };
+
+    let challenge = challenges::create(
+        &db.db,
+        interaction.user.id.get(),
+        opponent.get(),
+        &abbreviation,
+        time,
+    )
+    .await?;
+
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("challenge:accept:{}", challenge.id))
+            .label("Accept")
+            .style(ButtonStyle::Success),
+        CreateButton::new(format!("challenge:decline:{}", challenge.id))
+            .label("Decline")
+            .style(ButtonStyle::Danger),
+    ]);
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(format!(
+                        "<@{}> challenged <@{}> to a match on {}{}!",
+                        interaction.user.id,
+                        opponent,
+                        challenge.lb,
+                        challenge
+                            .time
+                            .map(|time| format!(" <t:{}:R>", time.unix_timestamp()))
+                            .unwrap_or_default(),
+                    ))
+                    .components(vec![buttons]),
+            ),
+        )
+        .await?;
+
+    //Results go to a thread under the challenge, or the channel if threads aren't allowed.
+    let message = interaction.get_response(&ctx.http).await?;
+    let thread = message
+        .channel_id
+        .create_thread_from_message(
+            &ctx.http,
+            message.id,
+            CreateThread::new(format!("Challenge #{}", challenge.id)),
+        )
+        .await
+        .map(|thread| thread.id)
+        .unwrap_or(message.channel_id);
+    challenges::set_thread(&db.db, challenge.id, thread.get()).await?;
+
+    Ok(())
+}
+
+///Handles the accept and decline buttons of a challenge. The custom id is
+///"challenge:<accept/decline>:<id>".
+pub async fn challenge_button(
+    ctx: &serenity::all::Context,
+    interaction: &ComponentInteraction,
+    args: &str,
+) -> Result<()> {
+    let (answer, id) = args.split_once(':').context("Missing challenge.")?;
+    let id: i64 = id.parse()?;
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let message =
+        challenges::answer(&db.db, id, interaction.user.id.get(), answer == "accept").await?;
+
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(message)
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    challenges::open_due(ctx).await?;
+
+    Ok(())
+}
+
+pub async fn h2h(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: Vec<&str>,
+) -> Result<()> {
+    if help_check(
+        ctx,
+        interaction,
+        &args,
+        concat!(
+            "Compares two players who played against each other. Separate the names with a ",
+            "comma. If only one name is given, they're compared with the \"user:\" option or ",
+            "your linked account. The leaderboard defaults to the one the first player played ",
+            "on most recently.\n\nUsage: h2h <name>, [name] [leaderboard abbreviation]",
+        ),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let (names, abbreviation) = split_leaderboard(&db.db, &args).await?;
+    let names = names.join(" ");
+    let mut names = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+    let a = PlayerQuery::Name(names.next().context("Missing player name.")?.to_string());
+    let b = player_query(
+        interaction,
+        &names.next().into_iter().collect::<Vec<&str>>(),
+    );
+
+    let abbreviation = match abbreviation {
+        Some(abbreviation) => abbreviation,
+        None => latest_leaderboard(&db.db, &a)
+            .await?
+            .context("This player hasn't played on any leaderboards.")?,
+    };
+
+    let h2h = fetch_head_to_head(ctx, &abbreviation, a, b).await?;
+
+    let meetings = h2h
+        .meetings
+        .iter()
+        .rev()
+        .take(RECENT_GAMES)
+        .map(|meeting| {
+            format!(
+                "`#{}` {} **{}** ({:.0}% to win)",
+                meeting.game_id,
+                meeting.day,
+                meeting.result.letter(),
+                meeting.win_probability * 100.
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let embed = CreateEmbed::new()
+        .title(format!(
+            "{} vs {}",
+            escaped(&h2h.a.name),
+            escaped(&h2h.b.name)
+        ))
+        .description(&h2h.leaderboard)
+        .field(
+            "Record",
+            format!(
+                "{}W {}L {}D",
+                h2h.count(GameResult::Win),
+                h2h.count(GameResult::Loss),
+                h2h.count(GameResult::Draw)
+            ),
+            true,
+        )
+        .field(
+            "Win probability",
+            format!(
+                "{:.0}% - {:.0}%",
+                h2h.win_probability * 100.,
+                (1. - h2h.win_probability) * 100.
+            ),
+            true,
+        )
+        .field(
+            "Recent meetings",
+            if meetings.is_empty() {
+                "None".to_string()
+            } else {
+                meetings
+            },
+            false,
+        );
+
+    interaction
+        .create_response(&ctx.http, embed_message(embed))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_head_to_head(
+    ctx: &serenity::all::Context,
+    abbreviation: &str,
+    a: PlayerQuery,
+    b: PlayerQuery,
+) -> Result<HeadToHead> {
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not found.")?;
+    let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, abbreviation).await?;
+
+    let (h2h, h2h_rx) = oneshot::channel();
+    leaderboard_tx
+        .send(LeaderboardMessage::HeadToHead { a, b, h2h })
+        .await?;
+
+    h2h_rx.await?
+}
+
+///Splits a trailing leaderboard abbreviation off of the arguments if there's more than one.
+async fn split_leaderboard<'a>(
+    db: &Pool<Postgres>,
+    args: &[&'a str],
+) -> Result<(Vec<&'a str>, Option<String>)> {
+    let abbreviations: Vec<String> = sqlx::query_scalar("SELECT abbreviation FROM leaderboard")
+        .fetch_all(db)
+        .await?;
+
+    let mut args = args.to_vec();
+    let abbreviation = match args.last() {
+        Some(last) if args.len() > 1 && abbreviations.iter().any(|a| a == last) => {
+            args.pop().map(str::to_string)
+        }
+        _ => None,
+    };
+
+    Ok((args, abbreviation))
+}
+
+///The leaderboard that a player most recently played on.
+async fn latest_leaderboard(db: &Pool<Postgres>, query: &PlayerQuery) -> Result<Option<String>> {
+    Ok(match query {
+        PlayerQuery::Name(name) => {
+            let query = sqlx::query_scalar(
+                "SELECT lb.abbreviation FROM lb_players l \
+                JOIN player_names n ON n.player_id = l.player_id \
+                JOIN leaderboard lb ON lb.id = l.lb_id \
+                WHERE n.name = $1 ORDER BY l.last_updated DESC LIMIT 1",
+            );
+            query.bind(name).fetch_optional(db).await?
+        }
+        PlayerQuery::Discord(discord_id) => {
+            let query = sqlx::query_scalar(
+                "SELECT lb.abbreviation FROM lb_players l \
+                JOIN players p ON p.id = l.player_id \
+                JOIN leaderboard lb ON lb.id = l.lb_id \
+                WHERE p.discord_id = $1 ORDER BY l.last_updated DESC LIMIT 1",
+            );
+            query.bind(*discord_id as i64).fetch_optional(db).await?
+        }
+        PlayerQuery::Id(id) => {
+            let query = sqlx::query_scalar(
+                "SELECT lb.abbreviation FROM lb_players l \
+                JOIN leaderboard lb ON lb.id = l.lb_id WHERE l.id = $1",
+            );
+            query.bind(id).fetch_optional(db).await?
+        }
+    })
+}
+
+fn rating_embed(profile: &PlayerProfile) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(escaped(&profile.player.name))
+        .description(&profile.leaderboard)
+        .field(
+            "Rating",
+            format!("{:.0}", profile.player.display_rating),
+            true,
+        )
+        .field("σ", format!("{:.0}", profile.player.rating_deviation), true)
+        .field(
+            "Rank",
+            if profile.player.provisional {
+                "Provisional".to_string()
+            } else {
+                format!("#{} of {}", profile.rank, profile.player_num)
+            },
+            true,
+        )
+        .field("Games", profile.games.len().to_string(), true)
+}
+
+///Gets the profile of the player named in the arguments, the "user:" option or the user
+///running the command in that order.
+pub async fn player_profile(
+    ctx: &serenity::all::Context,
+    interaction: &CommandInteraction,
+    args: &[&str],
+) -> Result<PlayerProfile> {
+    let abbreviation = args.first().context("Missing leaderboard abbreviation.")?;
+    let query = player_query(interaction, &args[1..]);
+
+    fetch_profile(ctx, abbreviation, query).await
+}
+
+pub async fn fetch_profile(
+    ctx: &serenity::all::Context,
+    abbreviation: &str,
+    query: PlayerQuery,
+) -> Result<PlayerProfile> {
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not found.")?;
+    let leaderboard_tx = bonk_bot.leaderboard_tx(ctx, abbreviation).await?;
+
+    let (profile, profile_rx) = oneshot::channel();
+    leaderboard_tx
+        .send(LeaderboardMessage::Profile { query, profile })
+        .await?;
+
+    profile_rx.await?
+}
+
+pub fn player_query(interaction: &CommandInteraction, name: &[&str]) -> PlayerQuery {
+    let name = name.join(" ");
+    if !name.is_empty() {
+        return PlayerQuery::Name(name);
+    }
+
+    let user = interaction
+        .data
+        .options
+        .iter()
+        .find(|o| o.name == "user")
+        .and_then(|o| match o.value {
+            CommandDataOptionValue::User(user) => Some(user),
+            _ => None,
+        })
+        .unwrap_or(interaction.user.id);
+
+    PlayerQuery::Discord(user.get())
+}
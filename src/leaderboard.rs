@@ -1,10 +1,17 @@
+pub mod glicko2;
+pub mod graph;
 pub mod openskill;
+pub mod stats;
+pub mod whr;
 
-use std::{f64, pin::Pin, sync::Arc, time::Duration};
+use std::{collections::HashMap, f64, pin::Pin, sync::Arc, time::Duration};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use serenity::all::ChannelId;
+use serenity::all::{
+    ChannelId, CreateActionRow, CreateEmbed, CreateEmbedFooter, CreateMessage, CreateSelectMenu,
+    CreateSelectMenuKind, CreateSelectMenuOption, EditMessage, Timestamp,
+};
 use sqlx::{
     prelude::FromRow,
     types::time::{Date, OffsetDateTime},
@@ -15,6 +22,10 @@ use tokio::{
     time,
 };
 
+use crate::players;
+use openskill::TeamPrediction;
+use stats::{HeadToHead, MapStats, PlayerProfile, PlayerQuery};
+
 #[derive(Deserialize, Serialize)]
 pub struct LeaderboardSettings {
     pub name: String,
@@ -25,17 +36,38 @@ pub struct LeaderboardSettings {
     pub unrated_deviation: f64,
     pub deviation_per_day: f64,
     pub cre: Option<f64>,
+    pub volatility: Option<f64>,
+    pub tau: Option<f64>,
+    pub soft_reset: Option<f64>,
+    pub season_placements: Option<usize>,
+    pub provisional_games: Option<usize>,
+    pub provisional_deviation: Option<f64>,
+    pub inactive_days: Option<i32>,
+    pub score_margin: Option<f64>,
+    pub map_ratings: Option<bool>,
+}
+
+impl LeaderboardSettings {
+    ///Provisional players aren't ranked until they've played enough games and their
+    ///rating deviation is low enough.
+    pub fn is_provisional(&self, games: usize, rating_deviation: f64) -> bool {
+        self.provisional_games.is_some_and(|min| games < min)
+            || self
+                .provisional_deviation
+                .is_some_and(|max| rating_deviation > max)
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub enum RatingAlgorithm {
     OpenSkill,
+    Glicko2,
+    WHR,
 }
 
 pub enum LeaderboardMessage {
     Update {
-        teams: Vec<Vec<String>>,
-        ties: Vec<bool>,
+        game: NewGame,
         match_str: oneshot::Sender<Result<String>>,
     },
     TopPlayers {
@@ -45,6 +77,43 @@ pub enum LeaderboardMessage {
         str: oneshot::Sender<Result<String>>,
         name: String,
     },
+    Profile {
+        query: PlayerQuery,
+        profile: oneshot::Sender<Result<PlayerProfile>>,
+    },
+    HeadToHead {
+        a: PlayerQuery,
+        b: PlayerQuery,
+        h2h: oneshot::Sender<Result<HeadToHead>>,
+    },
+    Recalculate {
+        str: oneshot::Sender<Result<String>>,
+    },
+    NewSeason {
+        hard_reset: bool,
+        str: oneshot::Sender<Result<String>>,
+    },
+    Predict {
+        teams: Vec<Vec<String>>,
+        prediction: oneshot::Sender<Result<Vec<TeamPrediction>>>,
+    },
+    Balance {
+        players: Vec<String>,
+        team_num: usize,
+        teams: oneshot::Sender<Result<Vec<Vec<usize>>>>,
+    },
+    ///Display ratings of players by name. Unrated players get the unrated display rating.
+    Ratings {
+        names: Vec<String>,
+        ratings: oneshot::Sender<Result<Vec<f64>>>,
+    },
+    Void {
+        game_id: i64,
+        str: oneshot::Sender<Result<String>>,
+    },
+    MapStats {
+        stats: oneshot::Sender<Result<Vec<MapStats>>>,
+    },
 }
 
 #[derive(FromRow, Clone, Debug)]
@@ -56,7 +125,140 @@ pub struct PlayerData {
     #[sqlx(skip)]
     pub old_rating: f64,
     pub rating_deviation: f64,
+    pub volatility: f64,
     pub last_updated: Date,
+    #[sqlx(default)]
+    pub last_played: Option<Date>,
+    #[sqlx(skip)]
+    pub provisional: bool,
+}
+
+///Players on a leaderboard sorted by display rating.
+pub struct Standings {
+    pub ranked: Vec<PlayerData>,
+    pub provisional: Vec<PlayerData>,
+    ///Players hidden from the rankings for not playing in inactive_days days.
+    pub inactive: Vec<PlayerData>,
+}
+
+impl Standings {
+    ///Splits players into ranked, provisional and inactive. Games are the number of games
+    ///each player played by id.
+    pub fn new(
+        settings: &LeaderboardSettings,
+        players: Vec<PlayerData>,
+        games: &HashMap<i64, usize>,
+        today: Date,
+    ) -> Standings {
+        let mut standings = Standings {
+            ranked: vec![],
+            provisional: vec![],
+            inactive: vec![],
+        };
+        for mut player in players {
+            let games = games.get(&player.id).copied().unwrap_or(0);
+            player.provisional = settings.is_provisional(games, player.rating_deviation);
+
+            let inactive = match (settings.inactive_days, player.last_played) {
+                (Some(days), Some(last_played)) => {
+                    today.to_julian_day() - last_played.to_julian_day() > days
+                }
+                _ => false,
+            };
+            if inactive {
+                standings.inactive.push(player);
+            } else if player.provisional {
+                standings.provisional.push(player);
+            } else {
+                standings.ranked.push(player);
+            }
+        }
+
+        standings
+    }
+}
+
+///A game to be rated.
+#[derive(Clone, Debug)]
+pub struct NewGame {
+    ///Teams in order of placement.
+    pub teams: Vec<Vec<String>>,
+    ///Whether each team tied with the next team.
+    pub ties: Vec<bool>,
+    ///Round score of each team in the same order as teams.
+    pub score: Option<Vec<f64>>,
+    pub rounds: Vec<GameRound>,
+    pub map: Option<GameMap>,
+    ///Maps struck before the game was played.
+    pub struck_maps: Vec<GameMap>,
+    ///Side each team started on. Empty when unknown.
+    pub sides: Vec<Option<i32>>,
+}
+
+///A bonk map from the raw map data in a room config.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GameMap {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "authorname", default)]
+    pub author: String,
+}
+
+///A round of a rated game from lb_game_rounds.
+#[derive(Clone, Debug)]
+pub struct GameRound {
+    ///Index of the team that won the round in placement order.
+    pub winner: Option<usize>,
+    ///Time since the previous round ended or the game started.
+    pub duration: Duration,
+}
+
+///A rated game loaded from lb_games and lb_game_teams. Teams are in order of placement.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub id: i64,
+    pub season_num: i32,
+    pub day: Date,
+    pub score: Option<Vec<f64>>,
+    pub ties: Vec<bool>,
+    pub map_id: Option<i64>,
+    pub teams: Vec<Vec<i64>>,
+    pub old_rating: Vec<Vec<f64>>,
+    pub new_rating: Vec<Vec<f64>>,
+    ///Rating deviations after the game. Empty for teams saved before deviations were stored.
+    pub new_deviation: Vec<Vec<f64>>,
+    pub sides: Vec<Option<i32>>,
+}
+
+impl GameRecord {
+    pub fn placements(&self) -> Vec<usize> {
+        placements(self.teams.len(), &self.ties)
+    }
+
+    pub fn team_of(&self, player_id: i64) -> Option<usize> {
+        self.teams.iter().position(|team| team.contains(&player_id))
+    }
+}
+
+///Placement of every team where tied teams share a placement. ties\[i\] is true when team i
+///tied with team i + 1.
+pub fn placements(teams: usize, ties: &[bool]) -> Vec<usize> {
+    let mut placements: Vec<usize> = Vec::new();
+    for i in 0..teams {
+        let tied = i > 0 && ties.get(i - 1) == Some(&true);
+        match placements.last() {
+            Some(&last) if tied => placements.push(last),
+            _ => placements.push(i),
+        }
+    }
+    placements
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub struct Season {
+    pub season_num: i32,
+    pub start: Date,
+    pub hard_reset: bool,
 }
 
 ///Buffer 10, blocking send
@@ -68,6 +270,7 @@ pub struct Leaderboard {
     id: i64,
     season: i32,
     update_timer: Pin<Box<time::Sleep>>,
+    decay_timer: Pin<Box<time::Sleep>>,
     needs_update: bool,
     can_update: bool,
 }
@@ -75,7 +278,15 @@ pub struct Leaderboard {
 const DISCORD_MARKDOWN: [char; 9] = ['\\', '*', '_', '~', '`', '>', ':', '#', '-'];
 const DISCORD_CHARACTER_LIMIT: usize = 2000;
 const LEADERBOARD_DISPLAYED_PLACEMENTS: usize = 300;
-const BONK_LB_DISPLAYED_PLACEMENTS: i32 = 5;
+const LEADERBOARD_PAGE_PLACEMENTS: usize = 20;
+const PROVISIONAL_DISPLAYED_PLAYERS: usize = 10;
+//Days since a player last played for the activity indicators.
+const ACTIVE_DAYS: i32 = 7;
+const RECENTLY_ACTIVE_DAYS: i32 = 30;
+const BONK_LB_DISPLAYED_PLACEMENTS: usize = 5;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+const DEFAULT_SOFT_RESET: f64 = 0.5;
+const DEFAULT_SEASON_PLACEMENTS: usize = 10;
 
 impl Leaderboard {
     pub async fn new(
@@ -133,6 +344,8 @@ impl Leaderboard {
             id,
             season,
             update_timer: Box::pin(time::sleep(Duration::MAX)),
+            //Catches up on any days missed while the leaderboard wasn't running.
+            decay_timer: Box::pin(time::sleep(Duration::ZERO)),
             needs_update: false,
             can_update: true,
         })
@@ -144,100 +357,181 @@ impl Leaderboard {
         // update_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
         let update_delay = 120;
 
-        match self.settings.algorithm {
-            RatingAlgorithm::OpenSkill => loop {
-                tokio::select! {
-                    message = self.rx.recv() => {
-                        let _ = match message {
-                            Some(LeaderboardMessage::Update {
-                                teams,
-                                ties,
-                                match_str,
-                            }) => _ = {
-                                _ = match_str.send(openskill::update(self, teams, ties).await);
-
-                                if self.can_update {
-                                    if let Err(e) = self.update_leaderboard().await {
-                                        println!("Error when updating leaderboard: {}", e);
-                                    };
-                                    self.update_timer = Box::pin(time::sleep(Duration::from_secs(update_delay)));
-                                    self.can_update = false;
-                                } else {
-                                    self.needs_update = true;
-                                }
-                            },
-                            Some(LeaderboardMessage::TopPlayers {str}) => {
-                                let Ok(players): Result<Vec<PlayerData>> =
-                                    sqlx::query_as(
-                                        "SELECT * from lb_players \
-                                        WHERE lb_id = $1 \
-                                        ORDER BY display_rating DESC \
-                                        LIMIT $2"
-                                    )
-                                    .bind(self.id)
-                                    .bind(BONK_LB_DISPLAYED_PLACEMENTS)
-                                    .fetch_all(self.db.as_ref())
-                                    .await.context("") else {
-                                        let _ = str.send(Err(anyhow!("Database query error.")));
-                                        continue
-                                    };
-
-                                let _ = str.send(Ok(
-                                    players
-                                        .iter()
-                                        .enumerate()
-                                        .map(|(i, p)|
-                                            format!(
-                                                "{}: {} ({:.0}, σ = {:.0})",
-                                                to_ordinal(i + 1),
-                                                p.name,
-                                                p.display_rating,
-                                                p.rating_deviation
-                                            )
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => {
+                    let _ = match message {
+                        Some(LeaderboardMessage::Update {
+                            mut game,
+                            match_str,
+                        }) => _ = {
+                            game.score = game.score.filter(|score| score.len() == game.teams.len());
+                            let result = match self.settings.algorithm {
+                                RatingAlgorithm::OpenSkill => openskill::update(self, game).await,
+                                RatingAlgorithm::Glicko2 => glicko2::update(self, game).await,
+                                RatingAlgorithm::WHR => whr::update(self, game).await,
+                            };
+                            _ = match_str.send(result);
+
+                            if self.can_update {
+                                if let Err(e) = self.update_leaderboard().await {
+                                    println!("Error when updating leaderboard: {}", e);
+                                };
+                                self.update_timer = Box::pin(time::sleep(Duration::from_secs(update_delay)));
+                                self.can_update = false;
+                            } else {
+                                self.needs_update = true;
+                            }
+                        },
+                        Some(LeaderboardMessage::TopPlayers {str}) => {
+                            let Ok(Standings { ranked: players, .. }) = self.standings().await else {
+                                let _ = str.send(Err(anyhow!("Database query error.")));
+                                continue
+                            };
+
+                            let _ = str.send(Ok(
+                                players
+                                    .iter()
+                                    .take(BONK_LB_DISPLAYED_PLACEMENTS)
+                                    .enumerate()
+                                    .map(|(i, p)|
+                                        format!(
+                                            "{}: {} ({:.0}, σ = {:.0})",
+                                            to_ordinal(i + 1),
+                                            p.name,
+                                            p.display_rating,
+                                            p.rating_deviation
                                         )
-                                        .collect::<Vec<String>>()
-                                        .join(", ")
-                                ));
-                            },
-                            Some(LeaderboardMessage::PlayerInfo {str, name}) => { self.player_info(str, name).await; },
-                            None => break,
-                        };
-                    },
-                    _ = self.update_timer.as_mut() => {
-                        if self.needs_update {
+                                    )
+                                    .collect::<Vec<String>>()
+                                    .join(", ")
+                            ));
+                        },
+                        Some(LeaderboardMessage::PlayerInfo {str, name}) => { self.player_info(str, name).await; },
+                        Some(LeaderboardMessage::Profile {query, profile}) => {
+                            _ = profile.send(stats::profile(self, &query).await);
+                        },
+                        Some(LeaderboardMessage::HeadToHead {a, b, h2h}) => {
+                            _ = h2h.send(stats::head_to_head(self, &a, &b).await);
+                        },
+                        Some(LeaderboardMessage::MapStats {stats}) => {
+                            _ = stats.send(stats::map_stats(self).await);
+                        },
+                        Some(LeaderboardMessage::Predict {teams, prediction}) => {
+                            _ = prediction.send(openskill::predict(self, teams).await);
+                        },
+                        Some(LeaderboardMessage::Balance {players, team_num, teams}) => {
+                            _ = teams.send(openskill::balance(self, players, team_num).await);
+                        },
+                        Some(LeaderboardMessage::Ratings {names, ratings}) => {
+                            _ = ratings.send(find_teams(self, vec![names]).await.map(|teams| {
+                                teams.concat().iter().map(|player| player.display_rating).collect()
+                            }));
+                        },
+                        Some(LeaderboardMessage::Void {game_id, str}) => {
+                            let result = self.void(game_id).await;
+                            let voided = result.is_ok();
+                            _ = str.send(result);
+
+                            if voided {
+                                if let Err(e) = self.update_leaderboard().await {
+                                    println!("Error when updating leaderboard: {}", e);
+                                };
+                            }
+                        },
+                        Some(LeaderboardMessage::Recalculate {str}) => {
+                            let result = self.recalculate(None).await;
+                            let recalculated = result.is_ok();
+                            _ = str.send(result);
+
+                            if recalculated {
+                                if let Err(e) = self.update_leaderboard().await {
+                                    println!("Error when updating leaderboard: {}", e);
+                                };
+                            }
+                        },
+                        Some(LeaderboardMessage::NewSeason {hard_reset, str}) => {
+                            let result = self.new_season(hard_reset).await;
+                            let started = result.is_ok();
+                            _ = str.send(result);
+
+                            if started {
+                                if let Err(e) = self.update_leaderboard().await {
+                                    println!("Error when updating leaderboard: {}", e);
+                                };
+                            }
+                        },
+                        None => break,
+                    };
+                },
+                _ = self.decay_timer.as_mut() => {
+                    match self.decay().await {
+                        Ok(0) => {}
+                        Ok(_) => {
                             if let Err(e) = self.update_leaderboard().await {
                                 println!("Error when updating leaderboard: {}", e);
                             };
-                            self.update_timer = Box::pin(time::sleep(Duration::from_secs(update_delay)));
-                            self.needs_update = false;
-                        } else {
-                            self.update_timer = Box::pin(time::sleep(Duration::MAX));
-                            self.can_update = true;
                         }
-                    },
-                }
-            },
+                        Err(e) => println!("Error when applying inactivity decay: {}", e),
+                    }
+                    self.decay_timer = Box::pin(time::sleep(until_next_day()));
+                },
+                _ = self.update_timer.as_mut() => {
+                    if self.needs_update {
+                        if let Err(e) = self.update_leaderboard().await {
+                            println!("Error when updating leaderboard: {}", e);
+                        };
+                        self.update_timer = Box::pin(time::sleep(Duration::from_secs(update_delay)));
+                        self.needs_update = false;
+                    } else {
+                        self.update_timer = Box::pin(time::sleep(Duration::MAX));
+                        self.can_update = true;
+                    }
+                },
+            }
         }
     }
 
     pub async fn save_game(
         &mut self,
         trans: &mut Transaction<'static, Postgres>,
-        teams: &Vec<Vec<PlayerData>>,
+        teams: &[Vec<PlayerData>],
         day: Date,
-        score: Option<&Vec<f64>>,
-        ties: Option<&Vec<bool>>,
+        game: &NewGame,
     ) -> Result<String> {
+        let score = game.score.as_ref();
+        let ties = Some(&game.ties);
+        let rounds = &game.rounds;
+
+        for map in game.map.iter().chain(&game.struck_maps) {
+            sqlx::query(
+                "INSERT INTO maps (id, name, author) VALUES ($1, $2, $3) \
+                ON CONFLICT (id) DO UPDATE SET name = $2, author = $3",
+            )
+            .bind(map.id)
+            .bind(&map.name)
+            .bind(&map.author)
+            .execute(&mut **trans)
+            .await?;
+        }
+
         let id: i64 = sqlx::query_scalar(
             "INSERT INTO lb_games \
-            (lb_id, season_num, day, score, ties) \
-            VALUES ($1, $2, $3, $4, $5) RETURNING id",
+            (lb_id, season_num, day, score, ties, map_id, struck_maps) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
         )
         .bind(self.id)
         .bind(self.season)
         .bind(day)
         .bind(score)
         .bind(ties)
+        .bind(game.map.as_ref().map(|map| map.id))
+        .bind(
+            game.struck_maps
+                .iter()
+                .map(|map| map.id)
+                .collect::<Vec<i64>>(),
+        )
         .fetch_one(&mut **trans)
         .await?;
 
@@ -245,22 +539,74 @@ impl Leaderboard {
             let player_ids: Vec<i64> = team.iter().map(|player| player.id).collect();
             let old_rating: Vec<f64> = team.iter().map(|player| player.old_rating).collect();
             let new_rating: Vec<f64> = team.iter().map(|player| player.display_rating).collect();
+            let new_deviation: Vec<f64> =
+                team.iter().map(|player| player.rating_deviation).collect();
 
             sqlx::query(
                 "INSERT INTO lb_game_teams \
-                (game_id, team, player_ids, old_rating, new_rating) \
-                VALUES ($1, $2, $3, $4, $5)",
+                (game_id, team, player_ids, old_rating, new_rating, new_deviation, side) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7)",
             )
             .bind(id)
             .bind(i as i32)
             .bind(player_ids)
             .bind(old_rating)
             .bind(new_rating)
+            .bind(new_deviation)
+            .bind(game.sides.get(i).copied().flatten())
             .execute(&mut **trans)
             .await?;
         }
 
-        let match_string = match_string(teams, score, ties);
+        sqlx::query(
+            "INSERT INTO lb_game_rounds (game_id, round, winner, duration) \
+            SELECT $1, * FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::DOUBLE PRECISION[])",
+        )
+        .bind(id)
+        .bind((0..rounds.len() as i32).collect::<Vec<i32>>())
+        .bind(
+            rounds
+                .iter()
+                .map(|round| round.winner.map(|winner| winner as i32))
+                .collect::<Vec<Option<i32>>>(),
+        )
+        .bind(
+            rounds
+                .iter()
+                .map(|round| round.duration.as_secs_f64())
+                .collect::<Vec<f64>>(),
+        )
+        .execute(&mut **trans)
+        .await?;
+
+        sqlx::query("UPDATE lb_players SET last_played = $1 WHERE id = ANY($2)")
+            .bind(day)
+            .bind(
+                teams
+                    .iter()
+                    .flatten()
+                    .map(|player| player.id)
+                    .collect::<Vec<i64>>(),
+            )
+            .execute(&mut **trans)
+            .await?;
+
+        let games = games_played(&mut **trans, self.id).await?;
+        let teams: Vec<Vec<PlayerData>> = teams
+            .iter()
+            .map(|team| {
+                team.iter()
+                    .map(|player| PlayerData {
+                        provisional: self.settings.is_provisional(
+                            games.get(&player.id).copied().unwrap_or(0),
+                            player.rating_deviation,
+                        ),
+                        ..player.clone()
+                    })
+                    .collect()
+            })
+            .collect();
+        let match_string = match_string(&teams, score, ties);
 
         let channel_id: Option<i64> =
             sqlx::query_scalar("SELECT match_channel FROM leaderboard WHERE id = $1")
@@ -269,7 +615,12 @@ impl Leaderboard {
                 .await?;
         if let Some(channel_id) = channel_id {
             let channel_id = ChannelId::new(channel_id as u64);
-            channel_id.say(&self.ctx.http, match_string.0).await?;
+            channel_id
+                .say(
+                    &self.ctx.http,
+                    format!("{}\n-# Game #{}", match_string.0, id),
+                )
+                .await?;
         }
 
         self.needs_update = true;
@@ -277,38 +628,459 @@ impl Leaderboard {
         Ok(match_string.1)
     }
 
-    async fn update_leaderboard(&self) -> Result<()> {
-        let mut players: Vec<PlayerData> =
-            sqlx::query_as("SELECT * from lb_players WHERE lb_id = $1")
+    ///Deletes a game and recalculates the leaderboard without it.
+    async fn void(&mut self, game_id: i64) -> Result<String> {
+        Ok(format!(
+            "Voided game #{}. {}",
+            game_id,
+            self.recalculate(Some(game_id)).await?
+        ))
+    }
+
+    ///Replays every game in order under the leaderboard's current settings.
+    ///Player ratings and the ratings stored with each game are rewritten.
+    ///A voided game is deleted in the same transaction so it stays if the replay fails.
+    async fn recalculate(&mut self, void: Option<i64>) -> Result<String> {
+        let settings: serde_json::Value =
+            sqlx::query_scalar("SELECT settings FROM leaderboard WHERE id = $1")
                 .bind(self.id)
-                .fetch_all(self.db.as_ref())
+                .fetch_one(self.db.as_ref())
                 .await?;
+        self.settings = serde_json::from_value(settings)?;
 
-        players.sort_by(|a, b| b.display_rating.total_cmp(&a.display_rating));
+        let mut trans = self.db.begin().await?;
 
-        let mut lb_strings: Vec<String> = vec![self.settings.name.clone()];
-        for (i, player) in players.iter().enumerate() {
-            if i >= LEADERBOARD_DISPLAYED_PLACEMENTS {
-                break;
+        if let Some(game_id) = void {
+            let deleted = sqlx::query("DELETE FROM lb_games WHERE id = $1 AND lb_id = $2")
+                .bind(game_id)
+                .bind(self.id)
+                .execute(&mut *trans)
+                .await?;
+            if deleted.rows_affected() == 0 {
+                return Err(anyhow!("Unable to find game #{}.", game_id));
+            }
+        }
+
+        let mut games = load_games(&mut *trans, self.id, None).await?;
+        let seasons = load_seasons(&mut *trans, self.id).await?;
+        let players: Vec<PlayerData> = sqlx::query_as("SELECT * FROM lb_players WHERE lb_id = $1")
+            .bind(self.id)
+            .fetch_all(&mut *trans)
+            .await?;
+
+        let today = OffsetDateTime::now_utc().date();
+        let mut players: HashMap<i64, PlayerData> = players
+            .into_iter()
+            .map(|player| {
+                let id = player.id;
+                (id, unrated_player(&self.settings, id, player.name, today))
+            })
+            .collect();
+        //Players are unrated until the day of their first game. Saved ratings line up with
+        //the game's player ids, so a game with a missing player can't be replayed.
+        for game in &games {
+            for player_id in game.teams.iter().flatten() {
+                let Some(player) = players.get_mut(player_id) else {
+                    return Err(anyhow!(
+                        "Game #{} has player {} who isn't on the leaderboard.",
+                        game.id,
+                        player_id
+                    ));
+                };
+                player.last_updated = player.last_updated.min(game.day);
+            }
+        }
+        let player_num = players.len();
+
+        if let RatingAlgorithm::WHR = self.settings.algorithm {
+            let hard_reset = last_hard_reset(&seasons);
+            games.retain(|game| game.season_num >= hard_reset);
+            let ratings = whr::whr(&self.settings, &games);
+
+            apply_ratings(&mut trans, &vec![players.values().cloned().collect()]).await?;
+            whr::save_ratings(self, &mut trans, &ratings).await?;
+
+            let cre = self.settings.cre.unwrap_or(0.);
+            for game in &games {
+                for (i, team) in game.teams.iter().enumerate() {
+                    let mut old_rating = vec![];
+                    let mut new_rating = vec![];
+                    let mut new_deviation = vec![];
+                    for player_id in team {
+                        let days = ratings.get(player_id).map(Vec::as_slice).unwrap_or(&[]);
+                        let unrated = players.get(player_id).map_or(0., |p| p.display_rating);
+                        let unrated_deviation =
+                            players.get(player_id).map_or(0., |p| p.rating_deviation);
+                        let idx = days.binary_search_by_key(&game.day, |day| day.day);
+                        let display = |idx: usize| {
+                            days.get(idx)
+                                .map_or(unrated, |day| day.rating - day.rating_deviation * cre)
+                        };
+                        match idx {
+                            Ok(idx) => {
+                                old_rating.push(idx.checked_sub(1).map_or(unrated, display));
+                                new_rating.push(display(idx));
+                                new_deviation.push(
+                                    days.get(idx)
+                                        .map_or(unrated_deviation, |day| day.rating_deviation),
+                                );
+                            }
+                            Err(_) => {
+                                old_rating.push(unrated);
+                                new_rating.push(unrated);
+                                new_deviation.push(unrated_deviation);
+                            }
+                        }
+                    }
+
+                    save_game_team(
+                        &mut trans,
+                        game.id,
+                        i,
+                        old_rating,
+                        new_rating,
+                        new_deviation,
+                    )
+                    .await?;
+                }
+            }
+        } else {
+            let mut season_idx = 0;
+            for game in &games {
+                while let Some(season) = seasons.get(season_idx + 1) {
+                    if season.season_num > game.season_num {
+                        break;
+                    }
+                    //Players who haven't played yet are unrated until their first game.
+                    for player in players.values_mut() {
+                        if player.last_updated <= season.start {
+                            reset_player(&self.settings, player, season);
+                        }
+                    }
+                    season_idx += 1;
+                }
+
+                let mut teams_data: Vec<Vec<PlayerData>> = game
+                    .teams
+                    .iter()
+                    .map(|team| {
+                        team.iter()
+                            .map(|player_id| {
+                                let mut player = players[player_id].clone();
+                                player.old_rating = player.display_rating;
+                                player
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                let before = teams_data.clone();
+                match self.settings.algorithm {
+                    RatingAlgorithm::OpenSkill => {
+                        openskill::reverse_pl(&self.settings, &game.ties, &mut teams_data, game.day)
+                    }
+                    RatingAlgorithm::Glicko2 => {
+                        glicko2::glicko2(&self.settings, &game.ties, &mut teams_data, game.day)
+                    }
+                    RatingAlgorithm::WHR => unreachable!(),
+                }
+                apply_score_margin(
+                    &self.settings,
+                    game.score.as_ref(),
+                    &before,
+                    &mut teams_data,
+                );
+
+                for (i, team) in teams_data.iter().enumerate() {
+                    let old_rating = team.iter().map(|player| player.old_rating).collect();
+                    let new_rating = team.iter().map(|player| player.display_rating).collect();
+                    let new_deviation = team.iter().map(|player| player.rating_deviation).collect();
+                    save_game_team(
+                        &mut trans,
+                        game.id,
+                        i,
+                        old_rating,
+                        new_rating,
+                        new_deviation,
+                    )
+                    .await?;
+
+                    for player in team {
+                        players.insert(player.id, player.clone());
+                    }
+                }
             }
 
-            let lb_string = lb_strings
-                .last_mut()
-                .context("Failed to generate leaderboard string.")?;
+            for season in seasons.iter().skip(season_idx + 1) {
+                for player in players.values_mut() {
+                    if player.last_updated <= season.start {
+                        reset_player(&self.settings, player, season);
+                    }
+                }
+            }
+
+            apply_ratings(&mut trans, &vec![players.into_values().collect()]).await?;
+        }
+
+        sqlx::query(
+            "UPDATE lb_players l SET last_played = (\
+            SELECT MAX(g.day) FROM lb_games g JOIN lb_game_teams t ON t.game_id = g.id \
+            WHERE g.lb_id = l.lb_id AND l.id = ANY(t.player_ids)) \
+            WHERE l.lb_id = $1",
+        )
+        .bind(self.id)
+        .execute(&mut *trans)
+        .await?;
+
+        trans.commit().await?;
+
+        Ok(format!(
+            "Recalculated {} game{} for {} player{}.",
+            games.len(),
+            if games.len() == 1 { "" } else { "s" },
+            player_num,
+            if player_num == 1 { "" } else { "s" },
+        ))
+    }
+
+    ///Archives the current season's standings and starts a new season.
+    ///A hard reset makes every player unrated and a soft reset pulls ratings toward the mean.
+    async fn new_season(&mut self, hard_reset: bool) -> Result<String> {
+        let mut trans = self.db.begin().await?;
+
+        let mut players: Vec<PlayerData> = sqlx::query_as(
+            "SELECT * FROM lb_players WHERE lb_id = $1 ORDER BY display_rating DESC",
+        )
+        .bind(self.id)
+        .fetch_all(&mut *trans)
+        .await?;
+        //Provisional players aren't placed in the final standings.
+        let games = games_played(&mut *trans, self.id).await?;
+        let ranked: Vec<&PlayerData> = players
+            .iter()
+            .filter(|player| {
+                !self.settings.is_provisional(
+                    games.get(&player.id).copied().unwrap_or(0),
+                    player.rating_deviation,
+                )
+            })
+            .collect();
+
+        sqlx::query(
+            "INSERT INTO lb_season_standings \
+            (lb_id, season_num, placement, lb_player_id, name, rating, rating_deviation) \
+            SELECT $1, $2, s.* FROM UNNEST($3::INTEGER[], $4::BIGINT[], $5::TEXT[], \
+            $6::DOUBLE PRECISION[], $7::DOUBLE PRECISION[]) AS s",
+        )
+        .bind(self.id)
+        .bind(self.season)
+        .bind((1..=ranked.len() as i32).collect::<Vec<i32>>())
+        .bind(ranked.iter().map(|p| p.id).collect::<Vec<i64>>())
+        .bind(
+            ranked
+                .iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<String>>(),
+        )
+        .bind(
+            ranked
+                .iter()
+                .map(|p| p.display_rating)
+                .collect::<Vec<f64>>(),
+        )
+        .bind(
+            ranked
+                .iter()
+                .map(|p| p.rating_deviation)
+                .collect::<Vec<f64>>(),
+        )
+        .execute(&mut *trans)
+        .await?;
+
+        let mut standings = format!(
+            "**{} season {} final standings**",
+            self.settings.name, self.season
+        );
+        let placements = self
+            .settings
+            .season_placements
+            .unwrap_or(DEFAULT_SEASON_PLACEMENTS);
+        for (i, player) in ranked.iter().take(placements).enumerate() {
             let player_str = format!(
-                "\n{}. {} ({:.0}, σ = {:.2})",
+                "\n{}. {} ({:.0}, σ = {:.0})",
                 i + 1,
-                player.name,
+                escaped(&player.name),
                 player.display_rating,
                 player.rating_deviation
             );
-            if lb_string.encode_utf16().count() + player_str.encode_utf16().count()
+            if standings.encode_utf16().count() + player_str.encode_utf16().count()
                 > DISCORD_CHARACTER_LIMIT
             {
-                lb_strings.push(player_str);
-            } else {
-                lb_string.push_str(&player_str);
+                break;
+            }
+            standings.push_str(&player_str);
+        }
+
+        let season = Season {
+            season_num: self.season + 1,
+            start: OffsetDateTime::now_utc().date(),
+            hard_reset,
+        };
+        sqlx::query(
+            "INSERT INTO lb_seasons \
+            (lb_id, season_num, start, hard_reset) \
+            VALUES ($1, $2, $3, $4)",
+        )
+        .bind(self.id)
+        .bind(season.season_num)
+        .bind(season.start)
+        .bind(season.hard_reset)
+        .execute(&mut *trans)
+        .await?;
+
+        //WHR ignores soft resets since every rating is recomputed from the game history.
+        let whr = matches!(self.settings.algorithm, RatingAlgorithm::WHR);
+        if hard_reset || !whr {
+            for player in &mut players {
+                reset_player(&self.settings, player, &season);
+            }
+            apply_ratings(&mut trans, &vec![players]).await?;
+        }
+        if hard_reset && whr {
+            sqlx::query(
+                "DELETE FROM lb_player_days_whr \
+                WHERE lb_player_id IN (SELECT id FROM lb_players WHERE lb_id = $1)",
+            )
+            .bind(self.id)
+            .execute(&mut *trans)
+            .await?;
+        }
+
+        let channel_id: i64 = sqlx::query_scalar("SELECT channel FROM leaderboard WHERE id = $1")
+            .bind(self.id)
+            .fetch_one(&mut *trans)
+            .await?;
+
+        trans.commit().await?;
+        self.season = season.season_num;
+
+        //The season has started, so a failed post shouldn't look like a failed command.
+        if let Err(e) = ChannelId::new(channel_id as u64)
+            .say(&self.ctx.http, standings)
+            .await
+        {
+            println!("Failed to post season {} standings: {e}", self.season - 1);
+        }
+
+        Ok(format!(
+            "Season {} started with a {} reset.",
+            self.season,
+            if hard_reset { "hard" } else { "soft" }
+        ))
+    }
+
+    ///Every player sorted by display rating split into ranked, provisional and inactive players.
+    pub async fn standings(&self) -> Result<Standings> {
+        let players: Vec<PlayerData> = sqlx::query_as(
+            "SELECT * from lb_players WHERE lb_id = $1 ORDER BY display_rating DESC",
+        )
+        .bind(self.id)
+        .fetch_all(self.db.as_ref())
+        .await?;
+        let games = games_played(self.db.as_ref(), self.id).await?;
+
+        Ok(Standings::new(
+            &self.settings,
+            players,
+            &games,
+            OffsetDateTime::now_utc().date(),
+        ))
+    }
+
+    ///Grows every player's rating deviation for the days they haven't played and lowers
+    ///their display rating to match. Ratings themselves are never changed.
+    ///Returns the number of players that were updated.
+    async fn decay(&mut self) -> Result<usize> {
+        let today = OffsetDateTime::now_utc().date();
+        let mut trans = self.db.begin().await?;
+
+        let mut players: Vec<PlayerData> =
+            sqlx::query_as("SELECT * from lb_players WHERE lb_id = $1 AND last_updated < $2")
+                .bind(self.id)
+                .bind(today)
+                .fetch_all(&mut *trans)
+                .await?;
+        for player in &mut players {
+            grow_deviation(&self.settings, player, today);
+            player.display_rating =
+                player.rating - player.rating_deviation * self.settings.cre.unwrap_or(0.);
+        }
+        let updated = players.len();
+        apply_ratings(&mut trans, &vec![players]).await?;
+
+        trans.commit().await?;
+
+        Ok(updated)
+    }
+
+    ///Keeps a single embed in the leaderboard channel showing the first page of placements.
+    ///Placements from the previous update are kept for the rank change arrows.
+    async fn update_leaderboard(&self) -> Result<()> {
+        let Standings {
+            ranked: players,
+            provisional,
+            inactive,
+        } = self.standings().await?;
+
+        //Provisional and inactive players don't have a placement.
+        let ids: Vec<i64> = players
+            .iter()
+            .chain(&provisional)
+            .chain(&inactive)
+            .map(|player| player.id)
+            .collect();
+        let placements: Vec<Option<i32>> = (1..=players.len() as i32)
+            .map(Some)
+            .chain(provisional.iter().chain(&inactive).map(|_| None))
+            .collect();
+        sqlx::query(
+            "UPDATE lb_players l SET last_placement = l.placement, placement = u.placement \
+            FROM UNNEST($1::BIGINT[], $2::INTEGER[]) AS u(id, placement) WHERE l.id = u.id",
+        )
+        .bind(ids)
+        .bind(placements)
+        .execute(self.db.as_ref())
+        .await?;
+
+        let (mut embed, components) = leaderboard_page(
+            self.db.as_ref(),
+            self.id,
+            &self.settings.abbreviation,
+            &self.settings.name,
+            0,
+        )
+        .await?;
+        if !provisional.is_empty() {
+            let mut list = provisional
+                .iter()
+                .take(PROVISIONAL_DISPLAYED_PLAYERS)
+                .map(|player| {
+                    format!(
+                        "**{}** {:.0} (σ = {:.0})",
+                        escaped(&player.name),
+                        player.display_rating,
+                        player.rating_deviation
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            if provisional.len() > PROVISIONAL_DISPLAYED_PLAYERS {
+                list += &format!(
+                    "\nand {} more",
+                    provisional.len() - PROVISIONAL_DISPLAYED_PLAYERS
+                );
             }
+            embed = embed.field("Provisional", list, false);
         }
 
         let channel_id: i64 = sqlx::query_scalar("SELECT channel FROM leaderboard WHERE id = $1")
@@ -324,29 +1096,46 @@ impl Leaderboard {
                 .fetch_one(self.db.as_ref())
                 .await?;
 
-        let mut new_messages: Vec<i64> = vec![];
-        for (i, message_id) in messages.iter().enumerate() {
-            let message = channel_id.message(&self.ctx.http, *message_id as u64).await;
-
-            if let Ok(message) = message {
-                if let Some(lb_string) = lb_strings.get(i) {
-                    if i == new_messages.len() && message.content == *lb_string {
-                        lb_strings.remove(i); //panics
-                        new_messages.push(*message_id);
-                    } else {
-                        message.delete(&self.ctx.http).await?;
-                    }
-                } else {
-                    message.delete(&self.ctx.http).await?;
-                }
+        //Older versions posted the leaderboard as several text messages.
+        for message_id in messages.iter().skip(1) {
+            if let Ok(message) = channel_id.message(&self.ctx.http, *message_id as u64).await {
+                message.delete(&self.ctx.http).await?;
             }
         }
 
-        for lb_string in lb_strings {
-            new_messages.push(channel_id.say(&self.ctx.http, lb_string).await?.id.get() as i64);
-        }
+        let message = match messages.first() {
+            Some(message_id) => channel_id
+                .message(&self.ctx.http, *message_id as u64)
+                .await
+                .ok(),
+            None => None,
+        };
+        let message_id = match message {
+            Some(mut message) => {
+                message
+                    .edit(
+                        &self.ctx.http,
+                        EditMessage::new()
+                            .content("")
+                            .embed(embed)
+                            .components(components),
+                    )
+                    .await?;
+                message.id
+            }
+            None => {
+                channel_id
+                    .send_message(
+                        &self.ctx.http,
+                        CreateMessage::new().embed(embed).components(components),
+                    )
+                    .await?
+                    .id
+            }
+        };
+
         sqlx::query("UPDATE leaderboard SET messages = $1 WHERE id = $2")
-            .bind(new_messages)
+            .bind(vec![message_id.get() as i64])
             .bind(self.id)
             .execute(self.db.as_ref())
             .await?;
@@ -355,46 +1144,474 @@ impl Leaderboard {
     }
 
     async fn player_info(&self, str: oneshot::Sender<Result<String>>, name: String) {
-        let Ok(players): Result<Vec<PlayerData>> = sqlx::query_as(
-            "SELECT * FROM lb_players WHERE lb_id = $1 ORDER BY display_rating DESC",
-        )
-        .bind(self.id)
-        .fetch_all(self.db.as_ref())
-        .await
-        .context("") else {
-            let _ = str.send(Err(anyhow!("Database query error.")));
-            return;
-        };
-
-        let Some(player) = players.iter().enumerate().find(|p| p.1.name == name) else {
-            let _ = str.send(Err(anyhow!("Unable to find player.")));
-            return;
-        };
-
-        let Ok((games,)): Result<(i64,)> =
-            sqlx::query_as("SELECT COUNT(*) FROM lb_game_teams where $1 = ANY(player_ids)")
-                .bind(player.1.id)
-                .fetch_one(self.db.as_ref())
-                .await
-                .context("")
-        else {
-            let _ = str.send(Err(anyhow!("Database query error.")));
-            return;
+        let profile = match stats::profile(self, &PlayerQuery::Name(name)).await {
+            Ok(profile) => profile,
+            Err(e) => {
+                let _ = str.send(Err(e));
+                return;
+            }
         };
+        let games = profile.games.len();
 
         let _ = str.send(Ok(format!(
-            "{} elo = {:.0} ({} out of {}), σ = {:.0}, {} game{} played.",
-            player.1.name,
-            player.1.display_rating,
-            to_ordinal(player.0 + 1),
-            players.len(),
-            player.1.rating_deviation,
+            "{} elo = {:.0} ({}), σ = {:.0}, {} game{} played.",
+            profile.player.name,
+            profile.player.display_rating,
+            if profile.player.provisional {
+                "provisional".to_string()
+            } else {
+                format!("{} out of {}", to_ordinal(profile.rank), profile.player_num)
+            },
+            profile.player.rating_deviation,
             games,
             if games == 1 { "" } else { "s" },
         )));
     }
 }
 
+///One page of the leaderboard channel embed along with a page selector if there's more than
+///one page. Placements are the ones saved by the last leaderboard update.
+pub async fn leaderboard_page(
+    db: &Pool<Postgres>,
+    lb_id: i64,
+    abbreviation: &str,
+    name: &str,
+    page: usize,
+) -> Result<(CreateEmbed, Vec<CreateActionRow>)> {
+    #[derive(FromRow)]
+    struct PlacementRow {
+        name: String,
+        display_rating: f64,
+        rating_deviation: f64,
+        last_played: Option<Date>,
+        placement: i32,
+        last_placement: Option<i32>,
+    }
+
+    let rows: Vec<PlacementRow> = sqlx::query_as(
+        "SELECT name, display_rating, rating_deviation, last_played, placement, last_placement \
+        FROM lb_players WHERE lb_id = $1 AND placement IS NOT NULL ORDER BY placement LIMIT $2",
+    )
+    .bind(lb_id)
+    .bind(LEADERBOARD_DISPLAYED_PLACEMENTS as i64)
+    .fetch_all(db)
+    .await?;
+
+    let pages = rows.len().div_ceil(LEADERBOARD_PAGE_PLACEMENTS).max(1);
+    let page = page.min(pages - 1);
+    let today = OffsetDateTime::now_utc().date();
+
+    let description = rows
+        .iter()
+        .skip(page * LEADERBOARD_PAGE_PLACEMENTS)
+        .take(LEADERBOARD_PAGE_PLACEMENTS)
+        .map(|row| {
+            let change = match row.last_placement {
+                Some(last) if last > row.placement => format!("▲{}", last - row.placement),
+                Some(last) if last < row.placement => format!("▼{}", row.placement - last),
+                Some(_) => "▬".to_string(),
+                None => "🆕".to_string(),
+            };
+            let inactive_days = row
+                .last_played
+                .map_or(i32::MAX, |day| today.to_julian_day() - day.to_julian_day());
+            let activity = if inactive_days <= ACTIVE_DAYS {
+                "🟢"
+            } else if inactive_days <= RECENTLY_ACTIVE_DAYS {
+                "🟡"
+            } else {
+                "⚪"
+            };
+
+            format!(
+                "`{}.` {} {} **{}** {:.0} (σ = {:.0})",
+                row.placement,
+                change,
+                activity,
+                escaped(&row.name),
+                row.display_rating,
+                row.rating_deviation
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let embed = CreateEmbed::new()
+        .title(name)
+        .description(if description.is_empty() {
+            "No rated players yet.".to_string()
+        } else {
+            description
+        })
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} · 🟢 played in the last {} days · 🟡 in the last {} days",
+            page + 1,
+            pages,
+            ACTIVE_DAYS,
+            RECENTLY_ACTIVE_DAYS
+        )))
+        .timestamp(Timestamp::now());
+
+    let mut components = vec![];
+    if pages > 1 {
+        let options = (0..pages)
+            .map(|i| {
+                let first = i * LEADERBOARD_PAGE_PLACEMENTS + 1;
+                let last = ((i + 1) * LEADERBOARD_PAGE_PLACEMENTS).min(rows.len());
+                CreateSelectMenuOption::new(
+                    format!("Page {} ({}-{})", i + 1, first, last),
+                    i.to_string(),
+                )
+                .default_selection(i == page)
+            })
+            .collect();
+        components.push(CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                format!("leaderboard:{}", abbreviation),
+                CreateSelectMenuKind::String { options },
+            )
+            .placeholder("Jump to page"),
+        ));
+    }
+
+    Ok((embed, components))
+}
+
+///Loads every game of a leaderboard in the order they were played.
+///If a player is given, only games with that player are loaded.
+pub async fn load_games<'c>(
+    db: impl sqlx::PgExecutor<'c>,
+    lb_id: i64,
+    player_id: Option<i64>,
+) -> Result<Vec<GameRecord>> {
+    #[derive(FromRow)]
+    struct GameTeamRow {
+        id: i64,
+        season_num: i32,
+        day: Date,
+        score: Option<Vec<f64>>,
+        ties: Option<Vec<bool>>,
+        map_id: Option<i64>,
+        player_ids: Vec<i64>,
+        old_rating: Vec<f64>,
+        new_rating: Vec<f64>,
+        new_deviation: Option<Vec<f64>>,
+        side: Option<i32>,
+    }
+
+    let rows: Vec<GameTeamRow> = sqlx::query_as(
+        "SELECT g.id, g.season_num, g.day, g.score, g.ties, g.map_id, t.player_ids, t.old_rating, \
+        t.new_rating, t.new_deviation, t.side FROM lb_games g JOIN lb_game_teams t ON t.game_id = g.id \
+        WHERE g.lb_id = $1 AND ($2::BIGINT IS NULL OR g.id IN \
+        (SELECT game_id FROM lb_game_teams WHERE $2 = ANY(player_ids))) \
+        ORDER BY g.id, t.team",
+    )
+    .bind(lb_id)
+    .bind(player_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut games: Vec<GameRecord> = Vec::new();
+    for row in rows {
+        match games.last_mut() {
+            Some(game) if game.id == row.id => {
+                game.teams.push(row.player_ids);
+                game.old_rating.push(row.old_rating);
+                game.new_rating.push(row.new_rating);
+                game.new_deviation
+                    .push(row.new_deviation.unwrap_or_default());
+                game.sides.push(row.side);
+            }
+            _ => games.push(GameRecord {
+                id: row.id,
+                season_num: row.season_num,
+                day: row.day,
+                score: row.score,
+                ties: row.ties.unwrap_or_default(),
+                map_id: row.map_id,
+                teams: vec![row.player_ids],
+                old_rating: vec![row.old_rating],
+                new_rating: vec![row.new_rating],
+                new_deviation: vec![row.new_deviation.unwrap_or_default()],
+                sides: vec![row.side],
+            }),
+        }
+    }
+
+    Ok(games)
+}
+
+///Number of games each player has played since the last hard reset by lb_players id.
+pub async fn games_played<'c>(
+    db: impl sqlx::PgExecutor<'c>,
+    lb_id: i64,
+) -> Result<HashMap<i64, usize>> {
+    let games: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT p.id, COUNT(*) FROM lb_games g JOIN lb_game_teams t ON t.game_id = g.id \
+        CROSS JOIN UNNEST(t.player_ids) AS p(id) \
+        WHERE g.lb_id = $1 AND g.season_num >= COALESCE( \
+        (SELECT MAX(season_num) FROM lb_seasons WHERE lb_id = $1 AND hard_reset), 0) \
+        GROUP BY p.id",
+    )
+    .bind(lb_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(games
+        .into_iter()
+        .map(|(id, games)| (id, games as usize))
+        .collect())
+}
+
+pub async fn load_seasons<'c>(db: impl sqlx::PgExecutor<'c>, lb_id: i64) -> Result<Vec<Season>> {
+    Ok(sqlx::query_as(
+        "SELECT season_num, start, hard_reset FROM lb_seasons \
+        WHERE lb_id = $1 ORDER BY season_num",
+    )
+    .bind(lb_id)
+    .fetch_all(db)
+    .await?)
+}
+
+///The most recent season that started with a hard reset.
+pub fn last_hard_reset(seasons: &[Season]) -> i32 {
+    seasons
+        .iter()
+        .filter(|season| season.hard_reset)
+        .map(|season| season.season_num)
+        .max()
+        .unwrap_or(0)
+}
+
+///Resets a player at the start of a season. A soft reset moves the rating toward the mean
+///rating and the deviation toward the unrated deviation by the soft_reset fraction.
+pub fn reset_player(settings: &LeaderboardSettings, player: &mut PlayerData, season: &Season) {
+    if season.hard_reset {
+        *player = unrated_player(settings, player.id, player.name.clone(), season.start);
+        return;
+    }
+
+    let soft_reset = settings.soft_reset.unwrap_or(DEFAULT_SOFT_RESET);
+    let unrated_deviation = settings.unrated_deviation * settings.rating_scale;
+    player.rating += (settings.mean_rating - player.rating) * soft_reset;
+    player.rating_deviation += (unrated_deviation - player.rating_deviation) * soft_reset;
+    player.display_rating = player.rating - player.rating_deviation * settings.cre.unwrap_or(0.);
+}
+
+///Adds deviation_per_day worth of variance for every day since the player was last updated.
+pub fn grow_deviation(settings: &LeaderboardSettings, player: &mut PlayerData, day: Date) {
+    if player.last_updated < day {
+        let day_num = day.to_julian_day() - player.last_updated.to_julian_day();
+        player.last_updated = day;
+
+        player.rating_deviation = (player.rating_deviation.powi(2)
+            + (settings.deviation_per_day * settings.rating_scale).powi(2) * f64::from(day_num))
+        .sqrt();
+    }
+}
+
+///Scales every player's rating change by the margin of the round score when score_margin
+///is set. A shutout scales changes by 1 + score_margin and a game won by a single round
+///scales them by close to 1 - score_margin. Before holds the players as they were before
+///the update.
+pub fn apply_score_margin(
+    settings: &LeaderboardSettings,
+    score: Option<&Vec<f64>>,
+    before: &[Vec<PlayerData>],
+    teams_data: &mut [Vec<PlayerData>],
+) {
+    let (Some(score_margin), Some(score)) = (settings.score_margin, score) else {
+        return;
+    };
+    let max = score.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let min = score.iter().copied().fold(f64::INFINITY, f64::min);
+    if !max.is_finite() || min < 0. || max <= min {
+        return;
+    }
+    let margin = (max - min) / (max + min);
+    let multiplier = (1. + score_margin * (2. * margin - 1.)).max(0.);
+
+    for (team, before) in teams_data.iter_mut().zip(before) {
+        for (player, before) in team.iter_mut().zip(before) {
+            player.rating = before.rating + (player.rating - before.rating) * multiplier;
+            player.display_rating =
+                player.rating - player.rating_deviation * settings.cre.unwrap_or(0.);
+        }
+    }
+}
+
+///A new player with the leaderboard's starting rating.
+pub fn unrated_player(
+    settings: &LeaderboardSettings,
+    id: i64,
+    name: String,
+    day: Date,
+) -> PlayerData {
+    let rating = settings.mean_rating;
+    let rating_deviation = settings.unrated_deviation * settings.rating_scale;
+    let display_rating = rating - rating_deviation * settings.cre.unwrap_or(0.);
+
+    PlayerData {
+        id,
+        name,
+        rating,
+        display_rating,
+        old_rating: display_rating,
+        rating_deviation,
+        volatility: settings.volatility.unwrap_or(DEFAULT_VOLATILITY),
+        last_updated: day,
+        last_played: None,
+        provisional: false,
+    }
+}
+
+pub async fn get_teams(
+    lb: &Leaderboard,
+    trans: &mut Transaction<'static, Postgres>,
+    teams: Vec<Vec<String>>,
+) -> Result<Vec<Vec<PlayerData>>> {
+    let today = OffsetDateTime::now_utc().date();
+
+    let mut teams_data: Vec<Vec<PlayerData>> = Vec::new();
+    for team in teams {
+        let mut team_data: Vec<PlayerData> = Vec::new();
+        for player in team {
+            let player_data_option: Option<PlayerData> = sqlx::query_as(
+                "SELECT l.id, l.name, l.rating, l.rating_deviation, l.volatility, l.display_rating, l.last_updated \
+                FROM lb_players l JOIN player_names n ON n.player_id = l.player_id \
+                WHERE n.name = $1 AND l.lb_id = $2",
+            )
+            .bind(&player)
+            .bind(lb.id)
+            .fetch_optional(&mut **trans)
+            .await?;
+
+            let player_data;
+            if let Some(mut data) = player_data_option {
+                data.old_rating = data.display_rating;
+                player_data = data;
+            } else {
+                let (player_id, name) = players::get_or_create(trans, &player).await?;
+                let mut data = unrated_player(&lb.settings, 0, name, today);
+
+                data.id = sqlx::query_scalar(
+                    "INSERT INTO lb_players \
+                    (lb_id, player_id, rating, rating_deviation, volatility, name, display_rating, last_updated) \
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+                )
+                .bind(lb.id)
+                .bind(player_id)
+                .bind(data.rating)
+                .bind(data.rating_deviation)
+                .bind(data.volatility)
+                .bind(&data.name)
+                .bind(data.display_rating)
+                .bind(today)
+                .fetch_one(&mut **trans)
+                .await?;
+
+                player_data = data;
+            }
+            team_data.push(player_data);
+        }
+        teams_data.push(team_data);
+    }
+
+    Ok(teams_data)
+}
+
+///Time until the next day starts in UTC.
+fn until_next_day() -> Duration {
+    let now = OffsetDateTime::now_utc();
+    let tomorrow = now
+        .date()
+        .next_day()
+        .unwrap_or(now.date())
+        .midnight()
+        .assume_utc();
+
+    Duration::try_from(tomorrow - now).unwrap_or(Duration::from_secs(24 * 60 * 60))
+}
+
+///Looks up teams without creating players. Players that aren't on the leaderboard
+///yet are unrated with an id of 0.
+pub async fn find_teams(lb: &Leaderboard, teams: Vec<Vec<String>>) -> Result<Vec<Vec<PlayerData>>> {
+    let today = OffsetDateTime::now_utc().date();
+
+    let mut teams_data: Vec<Vec<PlayerData>> = Vec::new();
+    for team in teams {
+        let mut team_data: Vec<PlayerData> = Vec::new();
+        for player in team {
+            let player_data_option: Option<PlayerData> = sqlx::query_as(
+                "SELECT l.id, l.name, l.rating, l.rating_deviation, l.volatility, l.display_rating, l.last_updated \
+                FROM lb_players l JOIN player_names n ON n.player_id = l.player_id \
+                WHERE n.name = $1 AND l.lb_id = $2",
+            )
+            .bind(&player)
+            .bind(lb.id)
+            .fetch_optional(lb.db.as_ref())
+            .await?;
+
+            let mut data = player_data_option
+                .unwrap_or_else(|| unrated_player(&lb.settings, 0, player, today));
+            data.old_rating = data.display_rating;
+            team_data.push(data);
+        }
+        teams_data.push(team_data);
+    }
+
+    Ok(teams_data)
+}
+
+pub async fn apply_ratings(
+    trans: &mut Transaction<'static, Postgres>,
+    teams_data: &Vec<Vec<PlayerData>>,
+) -> Result<()> {
+    for team in teams_data {
+        for player in team {
+            sqlx::query(
+                "UPDATE lb_players \
+                SET rating = $1, rating_deviation = $2, volatility = $3, display_rating = $4, last_updated = $5 \
+                WHERE id = $6",
+            )
+            .bind(player.rating)
+            .bind(player.rating_deviation)
+            .bind(player.volatility)
+            .bind(player.display_rating)
+            .bind(player.last_updated)
+            .bind(player.id)
+            .execute(&mut **trans)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+///Rewrites the ratings stored with one team of a game.
+async fn save_game_team(
+    trans: &mut Transaction<'static, Postgres>,
+    game_id: i64,
+    team: usize,
+    old_rating: Vec<f64>,
+    new_rating: Vec<f64>,
+    new_deviation: Vec<f64>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE lb_game_teams SET old_rating = $1, new_rating = $2, new_deviation = $3 \
+        WHERE game_id = $4 AND team = $5",
+    )
+    .bind(old_rating)
+    .bind(new_rating)
+    .bind(new_deviation)
+    .bind(game_id)
+    .bind(team as i32)
+    .execute(&mut **trans)
+    .await?;
+
+    Ok(())
+}
+
 pub fn match_string(
     teams: &Vec<Vec<PlayerData>>,
     score: Option<&Vec<f64>>,
@@ -424,13 +1641,7 @@ pub fn match_string(
             .filter_map(|team| team)
             .map(|team| {
                 team.iter()
-                    .map(|player| {
-                        format!(
-                            "{} {}",
-                            player.name,
-                            rating_string(player.display_rating, player.old_rating),
-                        )
-                    })
+                    .map(|player| format!("{} {}", player.name, rating_string(player),))
                     .collect::<Vec<String>>()
                     .join(", ")
             })
@@ -451,13 +1662,7 @@ pub fn match_string(
                 .filter_map(|team| team)
                 .map(|team| team.get(0))
                 .filter_map(|player| player)
-                .map(|player| {
-                    format!(
-                        "{} {}",
-                        escaped(&player.name),
-                        rating_string(player.display_rating, player.old_rating),
-                    )
-                })
+                .map(|player| format!("{} {}", escaped(&player.name), rating_string(player),))
                 .collect::<Vec<String>>()
                 .join("\n");
         } else {
@@ -469,11 +1674,7 @@ pub fn match_string(
                 .map(|team| {
                     team.iter()
                         .map(|player| {
-                            format!(
-                                "{} {}",
-                                escaped(&player.name),
-                                rating_string(player.display_rating, player.old_rating)
-                            )
+                            format!("{} {}", escaped(&player.name), rating_string(player))
                         })
                         .collect::<Vec<String>>()
                         .join("\n")
@@ -486,13 +1687,7 @@ pub fn match_string(
             .iter()
             .map(|team| {
                 team.iter()
-                    .map(|player| {
-                        format!(
-                            "{} {}",
-                            player.name,
-                            rating_string(player.display_rating, player.old_rating),
-                        )
-                    })
+                    .map(|player| format!("{} {}", player.name, rating_string(player),))
                     .collect::<Vec<String>>()
                     .join(", ")
             })
@@ -529,19 +1724,11 @@ pub fn match_string(
                         if let Some(false) | None = ties.get(0) {
                             game += "Winner: "
                         }
-                        game += &format!(
-                            "{} {}\n",
-                            escaped(&winner.name),
-                            rating_string(winner.display_rating, winner.old_rating),
-                        );
+                        game += &format!("{} {}\n", escaped(&winner.name), rating_string(winner),);
                         if let Some(false) | None = ties.get(0) {
                             game += "Loser: "
                         }
-                        game += &format!(
-                            "{} {}",
-                            escaped(&loser.name),
-                            rating_string(loser.display_rating, loser.old_rating),
-                        );
+                        game += &format!("{} {}", escaped(&loser.name), rating_string(loser),);
                     }
                 }
             } else {
@@ -559,11 +1746,7 @@ pub fn match_string(
                     game += &winners
                         .iter()
                         .map(|player| {
-                            format!(
-                                "{} {}",
-                                escaped(&player.name),
-                                rating_string(player.display_rating, player.old_rating),
-                            )
+                            format!("{} {}", escaped(&player.name), rating_string(player),)
                         })
                         .collect::<Vec<String>>()
                         .join("\n");
@@ -575,11 +1758,7 @@ pub fn match_string(
                     game += &losers
                         .iter()
                         .map(|player| {
-                            format!(
-                                "{} {}",
-                                escaped(&player.name),
-                                rating_string(player.display_rating, player.old_rating),
-                            )
+                            format!("{} {}", escaped(&player.name), rating_string(player),)
                         })
                         .collect::<Vec<String>>()
                         .join("\n");
@@ -603,7 +1782,7 @@ pub fn match_string(
                                 "{}: {} {}",
                                 placement,
                                 escaped(&player.name),
-                                rating_string(player.display_rating, player.old_rating),
+                                rating_string(player),
                             ))
                         } else {
                             return None;
@@ -630,11 +1809,7 @@ pub fn match_string(
                                 .1
                                 .iter()
                                 .map(|player| {
-                                    format!(
-                                        "{} {}",
-                                        escaped(&player.name),
-                                        rating_string(player.display_rating, player.old_rating),
-                                    )
+                                    format!("{} {}", escaped(&player.name), rating_string(player),)
                                 })
                                 .collect::<Vec<String>>()
                                 .join("\n")
@@ -648,19 +1823,24 @@ pub fn match_string(
     (game, summary)
 }
 
-fn rating_string(new: f64, old: f64) -> String {
-    let new = new.round();
-    let old = old.round();
+fn rating_string(player: &PlayerData) -> String {
+    let new = player.display_rating.round();
+    let old = player.old_rating.round();
 
     format!(
-        "({}, {}{})",
+        "({}, {}{}){}",
         new,
         if new >= old { "+" } else { "" },
-        new - old
+        new - old,
+        if player.provisional {
+            " (provisional)"
+        } else {
+            ""
+        }
     )
 }
 
-fn escaped(str: &String) -> String {
+pub fn escaped(str: &String) -> String {
     str.chars()
         .map(|c| {
             if DISCORD_MARKDOWN.contains(&c) {
@@ -673,7 +1853,7 @@ fn escaped(str: &String) -> String {
         .join("")
 }
 
-fn to_ordinal(num: usize) -> String {
+pub fn to_ordinal(num: usize) -> String {
     let output = num.to_string();
     if num % 100 == 11 || num % 100 == 12 || num % 100 == 13 {
         return output + "th";
@@ -0,0 +1,174 @@
+use std::f64;
+
+use anyhow::Result;
+use sqlx::types::time::{Date, OffsetDateTime};
+
+use crate::leaderboard::Leaderboard;
+
+use super::{
+    apply_ratings, apply_score_margin, get_teams, grow_deviation, placements, LeaderboardSettings,
+    NewGame, PlayerData,
+};
+
+const DEFAULT_TAU: f64 = 0.5;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+///Update ratings with Glicko-2
+pub async fn update(lb: &mut Leaderboard, game: NewGame) -> Result<String> {
+    let mut trans = lb.db.begin().await?;
+
+    let mut teams_data = get_teams(lb, &mut trans, game.teams.clone()).await?;
+
+    let today = OffsetDateTime::now_utc().date();
+
+    let before = teams_data.clone();
+    glicko2(&lb.settings, &game.ties, &mut teams_data, today);
+    apply_score_margin(&lb.settings, game.score.as_ref(), &before, &mut teams_data);
+
+    apply_ratings(&mut trans, &teams_data).await?;
+    let match_string = lb.save_game(&mut trans, &teams_data, today, &game).await?;
+
+    trans.commit().await?;
+
+    Ok(match_string)
+}
+
+///Rating update for Glicko-2 where each game is its own rating period.
+///Every player is rated against the averaged rating of each opposing team.
+pub fn glicko2(
+    settings: &LeaderboardSettings,
+    ties: &[bool],
+    teams_data: &mut [Vec<PlayerData>],
+    day: Date,
+) {
+    for team in &mut *teams_data {
+        for player in team {
+            grow_deviation(settings, player, day);
+        }
+    }
+
+    let scale = settings.rating_scale;
+    let tau = settings.tau.unwrap_or(DEFAULT_TAU);
+
+    let placements = placements(teams_data.len(), ties);
+
+    //(mu, phi) of each team on the Glicko-2 scale.
+    let team_ratings: Vec<(f64, f64)> = teams_data
+        .iter()
+        .map(|team| {
+            let len = team.len().max(1) as f64;
+            let mu = team
+                .iter()
+                .map(|player| (player.rating - settings.mean_rating) / scale)
+                .sum::<f64>()
+                / len;
+            let phi = (team
+                .iter()
+                .map(|player| (player.rating_deviation / scale).powi(2))
+                .sum::<f64>()
+                / len)
+                .sqrt();
+            (mu, phi)
+        })
+        .collect();
+
+    for (i, team) in teams_data.iter_mut().enumerate() {
+        let results: Vec<(f64, f64, f64)> = team_ratings
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(j, (mu, phi))| {
+                let score = match placements[i].cmp(&placements[j]) {
+                    std::cmp::Ordering::Less => 1.,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.,
+                };
+                (*mu, *phi, score)
+            })
+            .collect();
+
+        for player in team {
+            let (mu, phi, sigma) = rate(
+                (player.rating - settings.mean_rating) / scale,
+                player.rating_deviation / scale,
+                player.volatility,
+                tau,
+                &results,
+            );
+
+            player.rating = mu * scale + settings.mean_rating;
+            player.rating_deviation = phi * scale;
+            player.volatility = sigma;
+            player.display_rating =
+                player.rating - player.rating_deviation * settings.cre.unwrap_or(0.);
+        }
+    }
+}
+
+///Glicko-2 update for one player over a rating period.
+///Results are (opponent mu, opponent phi, score) and the output is (mu, phi, sigma).
+pub fn rate(
+    mu: f64,
+    phi: f64,
+    sigma: f64,
+    tau: f64,
+    results: &[(f64, f64, f64)],
+) -> (f64, f64, f64) {
+    if results.is_empty() {
+        return (mu, phi, sigma);
+    }
+
+    let g = |phi: f64| 1. / (1. + 3. * phi.powi(2) / f64::consts::PI.powi(2)).sqrt();
+    let e = |mu_j: f64, phi_j: f64| 1. / (1. + (-g(phi_j) * (mu - mu_j)).exp());
+
+    let mut v_inv = 0.;
+    let mut delta_sum = 0.;
+    for (mu_j, phi_j, score) in results {
+        let e = e(*mu_j, *phi_j);
+        v_inv += g(*phi_j).powi(2) * e * (1. - e);
+        delta_sum += g(*phi_j) * (score - e);
+    }
+    let v = 1. / v_inv.max(f64::EPSILON);
+    let delta = v * delta_sum;
+
+    //Illinois algorithm for the new volatility.
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        x.exp() * (delta.powi(2) - phi.powi(2) - v - x.exp())
+            / (2. * (phi.powi(2) + v + x.exp()).powi(2))
+            - (x - a) / tau.powi(2)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta.powi(2) > phi.powi(2) + v {
+        (delta.powi(2) - phi.powi(2) - v).ln()
+    } else {
+        let mut k = 1.;
+        while f(a - k * tau) < 0. {
+            k += 1.;
+        }
+        a - k * tau
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > CONVERGENCE_TOLERANCE {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0. {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    let new_sigma = (big_a / 2.).exp();
+    let phi_star = (phi.powi(2) + new_sigma.powi(2)).sqrt();
+    let new_phi = 1. / (1. / phi_star.powi(2) + 1. / v).sqrt();
+    let new_mu = mu + new_phi.powi(2) * delta_sum;
+
+    (new_mu, new_phi, new_sigma)
+}
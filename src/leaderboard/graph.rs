@@ -0,0 +1,160 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+use plotters::prelude::*;
+use sqlx::types::time::Date;
+
+use super::stats::PlayerProfile;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+const FONT: &str = "sans-serif";
+const DEFAULT_FONT_PATH: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+const LINE_COLOR: RGBColor = RGBColor(88, 101, 242);
+const SEASON_COLOR: RGBColor = RGBColor(237, 66, 69);
+
+///Fonts are read once from GRAPH_FONT or a common system path.
+fn load_font() -> Result<()> {
+    static FONT_LOADED: OnceLock<Result<(), String>> = OnceLock::new();
+
+    FONT_LOADED
+        .get_or_init(|| {
+            let path = dotenv::var("GRAPH_FONT").unwrap_or_else(|_| DEFAULT_FONT_PATH.to_string());
+            let bytes = std::fs::read(&path)
+                .map_err(|e| format!("Unable to read graph font {}: {}", path, e))?;
+            plotters::style::register_font(FONT, FontStyle::Normal, Box::leak(bytes.into()))
+                .map_err(|_| format!("Invalid graph font {}.", path))
+        })
+        .clone()
+        .map_err(|e| anyhow!(e))
+}
+
+///Draws a player's rating over time as a PNG with a band of one rating deviation
+///around it and a line at the start of every season.
+pub fn rating_graph(profile: &PlayerProfile) -> Result<Vec<u8>> {
+    let Some(first) = profile.games.first() else {
+        return Err(anyhow!("No games found."));
+    };
+    load_font()?;
+
+    //The x axis is in days. Games played on the same day are spread evenly across it.
+    let times: Vec<f64> = profile
+        .games
+        .chunk_by(|a, b| a.game.day == b.game.day)
+        .flat_map(|games| {
+            let day = games[0].game.day.to_julian_day() as f64;
+            (1..=games.len()).map(move |i| day + i as f64 / (games.len() + 1) as f64)
+        })
+        .collect();
+    let start = first.game.day.to_julian_day() as f64;
+
+    let ratings: Vec<(f64, f64)> = std::iter::once((start, first.old_rating))
+        .chain(
+            profile
+                .games
+                .iter()
+                .zip(&times)
+                .map(|(game, x)| (*x, game.new_rating)),
+        )
+        .collect();
+    let bands: Vec<Vec<(f64, f64, f64)>> = profile
+        .games
+        .iter()
+        .zip(&times)
+        .map(|(game, x)| {
+            game.new_deviation
+                .map(|deviation| (*x, game.new_rating, deviation))
+        })
+        .collect::<Vec<Option<(f64, f64, f64)>>>()
+        .split(Option::is_none)
+        .map(|band| band.iter().flatten().copied().collect())
+        .filter(|band: &Vec<(f64, f64, f64)>| !band.is_empty())
+        .collect();
+    let seasons: Vec<(f64, i32)> = profile
+        .games
+        .windows(2)
+        .enumerate()
+        .filter(|(_, games)| games[0].game.season_num != games[1].game.season_num)
+        .map(|(i, games)| ((times[i] + times[i + 1]) / 2., games[1].game.season_num))
+        .collect();
+
+    let low = bands
+        .iter()
+        .flatten()
+        .map(|(_, rating, deviation)| rating - deviation)
+        .chain(ratings.iter().map(|(_, rating)| *rating))
+        .fold(f64::INFINITY, f64::min);
+    let high = bands
+        .iter()
+        .flatten()
+        .map(|(_, rating, deviation)| rating + deviation)
+        .chain(ratings.iter().map(|(_, rating)| *rating))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let margin = ((high - low) * 0.05).max(1.);
+    let end = times.last().map_or(start, |x| x.ceil()).max(start + 1.);
+
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(
+                format!("{} ({})", profile.player.name, profile.leaderboard),
+                (FONT, 24),
+            )
+            .margin(10)
+            .x_label_area_size(35)
+            .y_label_area_size(55)
+            .build_cartesian_2d(start..end, (low - margin)..(high + margin))?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Date")
+            .y_desc("Rating")
+            .x_labels(6)
+            .x_label_formatter(&|x| {
+                Date::from_julian_day(x.floor() as i32)
+                    .map(|day| day.to_string())
+                    .unwrap_or_default()
+            })
+            .y_label_formatter(&|y| format!("{:.0}", y))
+            .label_style((FONT, 14))
+            .draw()?;
+
+        for band in &bands {
+            let points: Vec<(f64, f64)> = band
+                .iter()
+                .map(|(x, rating, deviation)| (*x, rating + deviation))
+                .chain(
+                    band.iter()
+                        .rev()
+                        .map(|(x, rating, deviation)| (*x, rating - deviation)),
+                )
+                .collect();
+            chart.draw_series(std::iter::once(Polygon::new(points, LINE_COLOR.mix(0.2))))?;
+        }
+
+        for (x, season_num) in &seasons {
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![(*x, low - margin), (*x, high + margin)],
+                SEASON_COLOR.stroke_width(2),
+            )))?;
+            chart.draw_series(std::iter::once(Text::new(
+                format!("S{}", season_num),
+                (*x, high + margin),
+                (FONT, 12).into_font().color(&SEASON_COLOR),
+            )))?;
+        }
+
+        chart.draw_series(LineSeries::new(ratings, LINE_COLOR.stroke_width(2)))?;
+
+        root.present()?;
+    }
+
+    let mut png = vec![];
+    PngEncoder::new(&mut png).write_image(&buffer, WIDTH, HEIGHT, ColorType::Rgb8)?;
+
+    Ok(png)
+}
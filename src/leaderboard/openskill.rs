@@ -1,54 +1,350 @@
 use std::f64;
 
 use anyhow::Result;
-use sqlx::{types::time::OffsetDateTime, Postgres, Transaction};
+use sqlx::types::time::{Date, OffsetDateTime};
 
 use crate::leaderboard::Leaderboard;
 
-use super::{LeaderboardSettings, PlayerData};
+use super::{
+    apply_ratings, apply_score_margin, find_teams, get_teams, glicko2, grow_deviation,
+    LeaderboardSettings, NewGame, PlayerData, RatingAlgorithm,
+};
+
+///Games with up to this many players try every split of teams when balancing.
+const EXHAUSTIVE_BALANCE_PLAYERS: usize = 12;
+
+///What a team stands to gain or lose before a game.
+#[derive(Clone, Debug)]
+pub struct TeamPrediction {
+    pub names: Vec<String>,
+    pub win_probability: f64,
+    ///Average display rating change of the team's players if the team places first.
+    pub win_change: Option<f64>,
+    ///Average display rating change of the team's players if the team places last.
+    pub loss_change: Option<f64>,
+}
 
 ///Update ratings with Weng-Lin
-pub async fn update(
-    lb: &mut Leaderboard,
-    teams: Vec<Vec<String>>,
-    ties: Vec<bool>,
-) -> Result<String> {
+pub async fn update(lb: &mut Leaderboard, game: NewGame) -> Result<String> {
     let mut trans = lb.db.begin().await?;
 
-    let mut teams_data = get_teams(lb, &mut trans, teams).await?;
+    let mut teams_data = get_teams(lb, &mut trans, game.teams.clone()).await?;
 
     let today = OffsetDateTime::now_utc().date();
 
-    reverse_pl(&lb.settings, &ties, &mut teams_data);
+    let before = teams_data.clone();
+    reverse_pl(&lb.settings, &game.ties, &mut teams_data, today);
+    apply_score_margin(&lb.settings, game.score.as_ref(), &before, &mut teams_data);
 
     apply_ratings(&mut trans, &teams_data).await?;
-    let match_string = lb
-        .save_game(&mut trans, &teams_data, today, None, Some(&ties))
-        .await?;
+    let match_string = lb.save_game(&mut trans, &teams_data, today, &game).await?;
 
     trans.commit().await?;
 
     Ok(match_string)
 }
 
+///Predict a game between teams without saving anything.
+pub async fn predict(lb: &Leaderboard, teams: Vec<Vec<String>>) -> Result<Vec<TeamPrediction>> {
+    let teams_data = find_teams(lb, teams).await?;
+    let today = OffsetDateTime::now_utc().date();
+
+    Ok(team_predictions(&lb.settings, &teams_data, today))
+}
+
+///Chance of each team placing first along with the rating changes for a win and for a
+///loss with the leaderboard's algorithm. Other teams are tied in both outcomes and the
+///changes are for a shutout, so they're the most a team can gain or lose with a score
+///margin. WHR has no changes since a game can move every rating in the player's history.
+pub fn team_predictions(
+    settings: &LeaderboardSettings,
+    teams_data: &[Vec<PlayerData>],
+    day: Date,
+) -> Vec<TeamPrediction> {
+    let mut grown = teams_data.to_vec();
+    for team in &mut grown {
+        for player in team {
+            grow_deviation(settings, player, day);
+        }
+    }
+
+    let probabilities = match settings.algorithm {
+        RatingAlgorithm::OpenSkill => win_probabilities(
+            settings,
+            &grown
+                .iter()
+                .map(|team| {
+                    (
+                        team.iter().map(|player| player.rating).sum(),
+                        team.iter()
+                            .map(|player| player.rating_deviation.powi(2))
+                            .sum(),
+                    )
+                })
+                .collect::<Vec<(f64, f64)>>(),
+        ),
+        RatingAlgorithm::Glicko2 | RatingAlgorithm::WHR => {
+            average_win_probabilities(settings, &grown)
+        }
+    };
+
+    //Average display rating change of the team at idx after a game with the given order.
+    let change = |order: Vec<usize>, ties: Vec<bool>, idx: usize| {
+        let mut ordered: Vec<Vec<PlayerData>> =
+            order.iter().map(|i| teams_data[*i].clone()).collect();
+        let before = ordered.clone();
+        match settings.algorithm {
+            RatingAlgorithm::OpenSkill => reverse_pl(settings, &ties, &mut ordered, day),
+            RatingAlgorithm::Glicko2 => glicko2::glicko2(settings, &ties, &mut ordered, day),
+            RatingAlgorithm::WHR => return None,
+        }
+        let mut shutout = vec![0.; order.len()];
+        shutout[0] = 1.;
+        apply_score_margin(settings, Some(&shutout), &before, &mut ordered);
+
+        let pos = order.iter().position(|i| *i == idx)?;
+        let team = &ordered[pos];
+        Some(
+            team.iter()
+                .map(|player| player.display_rating - player.old_rating)
+                .sum::<f64>()
+                / team.len().max(1) as f64,
+        )
+    };
+
+    let len = teams_data.len();
+    (0..len)
+        .map(|i| {
+            let others = (0..len).filter(|j| *j != i);
+            let mut win_ties = vec![false];
+            win_ties.extend(vec![true; len.saturating_sub(2)]);
+            let mut loss_ties = vec![true; len.saturating_sub(2)];
+            loss_ties.push(false);
+
+            TeamPrediction {
+                names: teams_data[i]
+                    .iter()
+                    .map(|player| player.name.clone())
+                    .collect(),
+                win_probability: probabilities[i],
+                win_change: change(
+                    std::iter::once(i).chain(others.clone()).collect(),
+                    win_ties,
+                    i,
+                ),
+                loss_change: change(others.chain(std::iter::once(i)).collect(), loss_ties, i),
+            }
+        })
+        .collect()
+}
+
+///Chance of each team placing first for Glicko-2 and WHR, which rate a team by its
+///players' average. Two teams match stats::win_probability and more teams share the
+///chances in proportion to each team's strength.
+fn average_win_probabilities(
+    settings: &LeaderboardSettings,
+    teams: &[Vec<PlayerData>],
+) -> Vec<f64> {
+    let scale = settings.rating_scale;
+    let average = |team: &Vec<PlayerData>, value: fn(&PlayerData) -> f64| {
+        team.iter().map(value).sum::<f64>() / team.len().max(1) as f64
+    };
+    let ratings: Vec<f64> = teams
+        .iter()
+        .map(|team| average(team, |player| player.rating))
+        .collect();
+    let g = match settings.algorithm {
+        RatingAlgorithm::Glicko2 => {
+            let phi_2 = teams
+                .iter()
+                .map(|team| average(team, |player| player.rating_deviation.powi(2)))
+                .sum::<f64>()
+                / scale.powi(2);
+            1. / (1. + 3. * phi_2 / std::f64::consts::PI.powi(2)).sqrt()
+        }
+        _ => 1.,
+    };
+    //Shifted by the highest rating so exp doesn't overflow.
+    let max_rating = ratings.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let strengths: Vec<f64> = ratings
+        .iter()
+        .map(|rating| (g * (rating - max_rating) / scale).exp())
+        .collect();
+    let sum: f64 = strengths.iter().sum();
+
+    strengths.iter().map(|strength| strength / sum).collect()
+}
+
+///Chance of each team placing first under Plackett-Luce. Teams are given as the sum of
+///their players' ratings and the sum of their variances.
+fn win_probabilities(settings: &LeaderboardSettings, teams: &[(f64, f64)]) -> Vec<f64> {
+    let beta_2 = settings.rating_scale.powi(2);
+    let c = teams
+        .iter()
+        .map(|(_, variance)| variance + beta_2)
+        .sum::<f64>()
+        .sqrt()
+        .max(f64::EPSILON);
+    //Shifted by the highest rating so exp doesn't overflow.
+    let max_rating = teams
+        .iter()
+        .map(|(rating, _)| *rating)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let exp_rs: Vec<f64> = teams
+        .iter()
+        .map(|(rating, _)| ((rating - max_rating) / c).exp())
+        .collect();
+    let exp_sum: f64 = exp_rs.iter().sum();
+
+    exp_rs.iter().map(|exp_r| exp_r / exp_sum).collect()
+}
+
+///Splits players into even teams for a game between the players' names.
+pub async fn balance(
+    lb: &Leaderboard,
+    players: Vec<String>,
+    team_num: usize,
+) -> Result<Vec<Vec<usize>>> {
+    let players = find_teams(lb, vec![players]).await?.concat();
+    let today = OffsetDateTime::now_utc().date();
+
+    Ok(balanced_teams(&lb.settings, players, team_num, today))
+}
+
+///Splits players into team_num teams of the same size with the smallest gap between the
+///highest and lowest chance to win. Every split is tried for small games and larger games
+///swap players between teams until no swap narrows the gap. Returns indices into players.
+pub fn balanced_teams(
+    settings: &LeaderboardSettings,
+    mut players: Vec<PlayerData>,
+    team_num: usize,
+    day: Date,
+) -> Vec<Vec<usize>> {
+    for player in &mut players {
+        grow_deviation(settings, player, day);
+    }
+    let team_num = team_num.clamp(1, players.len().max(1));
+    let team_size = players.len() / team_num;
+
+    let gap = |teams: &[Vec<usize>]| {
+        let probabilities = win_probabilities(
+            settings,
+            &teams
+                .iter()
+                .map(|team| {
+                    (
+                        team.iter().map(|i| players[*i].rating).sum(),
+                        team.iter()
+                            .map(|i| players[*i].rating_deviation.powi(2))
+                            .sum(),
+                    )
+                })
+                .collect::<Vec<(f64, f64)>>(),
+        );
+        probabilities
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max)
+            - probabilities.iter().copied().fold(f64::INFINITY, f64::min)
+    };
+
+    if players.len() <= EXHAUSTIVE_BALANCE_PLAYERS {
+        let mut best = (f64::INFINITY, vec![]);
+        split_teams(
+            0,
+            team_size * team_num,
+            team_size,
+            &mut vec![vec![]; team_num],
+            &mut best,
+            &gap,
+        );
+        return best.1;
+    }
+
+    //Snake draft by rating as a starting point.
+    let mut order = (0..team_size * team_num).collect::<Vec<usize>>();
+    order.sort_by(|a, b| players[*b].rating.total_cmp(&players[*a].rating));
+    let mut teams = vec![vec![]; team_num];
+    for (pick, player) in order.into_iter().enumerate() {
+        let idx = pick % team_num;
+        let team = if (pick / team_num).is_multiple_of(2) {
+            idx
+        } else {
+            team_num - 1 - idx
+        };
+        teams[team].push(player);
+    }
+
+    let mut best_gap = gap(&teams);
+    'improve: loop {
+        for a in 0..team_num {
+            for b in a + 1..team_num {
+                for i in 0..team_size {
+                    for j in 0..team_size {
+                        let mut swapped = teams.clone();
+                        swapped[a][i] = teams[b][j];
+                        swapped[b][j] = teams[a][i];
+                        let swapped_gap = gap(&swapped);
+                        if swapped_gap < best_gap - f64::EPSILON {
+                            teams = swapped;
+                            best_gap = swapped_gap;
+                            continue 'improve;
+                        }
+                    }
+                }
+            }
+        }
+        break;
+    }
+
+    teams
+}
+
+///Tries every way of putting players from player onwards into teams. Empty teams are
+///interchangeable, so only the first one is tried.
+fn split_teams(
+    player: usize,
+    player_num: usize,
+    team_size: usize,
+    teams: &mut Vec<Vec<usize>>,
+    best: &mut (f64, Vec<Vec<usize>>),
+    gap: &dyn Fn(&[Vec<usize>]) -> f64,
+) {
+    if player == player_num {
+        let teams_gap = gap(teams);
+        if teams_gap < best.0 {
+            *best = (teams_gap, teams.clone());
+        }
+        return;
+    }
+
+    let mut tried_empty = false;
+    for team in 0..teams.len() {
+        if teams[team].len() >= team_size {
+            continue;
+        }
+        if teams[team].is_empty() {
+            if tried_empty {
+                continue;
+            }
+            tried_empty = true;
+        }
+        teams[team].push(player);
+        split_teams(player + 1, player_num, team_size, teams, best, gap);
+        teams[team].pop();
+    }
+}
+
 ///Rating update for Weng-Lin Bradley-Terry reverse Plackett-Luce (rankings and rating updates are reversed)
 pub fn reverse_pl(
     settings: &LeaderboardSettings,
     ties: &Vec<bool>,
     teams_data: &mut Vec<Vec<PlayerData>>,
+    day: Date,
 ) {
-    let today = OffsetDateTime::now_utc().date();
     for team in &mut *teams_data {
         for player in team {
-            if player.last_updated < today {
-                player.last_updated = today;
-                let day_num = today.to_julian_day() - player.last_updated.to_julian_day();
-
-                player.rating_deviation = (player.rating_deviation.powi(2)
-                    + (settings.deviation_per_day * settings.rating_scale).powi(2)
-                        * f64::from(day_num))
-                .sqrt();
-            }
+            grow_deviation(settings, player, day);
         }
     }
 
@@ -179,87 +475,3 @@ pub fn reverse_pl(
         }
     }
 }
-
-async fn get_teams(
-    lb: &Leaderboard,
-    trans: &mut Transaction<'static, Postgres>,
-    teams: Vec<Vec<String>>,
-) -> Result<Vec<Vec<PlayerData>>> {
-    let today = OffsetDateTime::now_utc().date();
-
-    let mut teams_data: Vec<Vec<PlayerData>> = Vec::new();
-    for team in teams {
-        let mut team_data: Vec<PlayerData> = Vec::new();
-        for player in team {
-            let player_data_option: Option<PlayerData> = sqlx::query_as(
-                "SELECT id, name, rating, rating_deviation, display_rating, last_updated FROM lb_players WHERE name = $1 AND lb_id = $2",
-            )
-            .bind(&player)
-            .bind(lb.id)
-            .fetch_optional(&mut **trans)
-            .await?;
-
-            let player_data;
-            if let Some(mut data) = player_data_option {
-                data.old_rating = data.display_rating;
-                player_data = data;
-            } else {
-                let rating = lb.settings.mean_rating;
-                let rating_deviation = lb.settings.unrated_deviation * lb.settings.rating_scale;
-                let display_rating = rating - rating_deviation * lb.settings.cre.unwrap_or(0.);
-
-                let player_id: i64 = sqlx::query_scalar(
-                    "INSERT INTO lb_players \
-                    (lb_id, rating, rating_deviation, name, display_rating, last_updated) \
-                    VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
-                )
-                .bind(lb.id)
-                .bind(rating)
-                .bind(rating_deviation)
-                .bind(&player)
-                .bind(display_rating)
-                .bind(today)
-                .fetch_one(&mut **trans)
-                .await?;
-
-                player_data = PlayerData {
-                    id: player_id,
-                    name: player,
-                    rating,
-                    display_rating,
-                    old_rating: display_rating,
-                    rating_deviation,
-                    last_updated: today,
-                }
-            }
-            team_data.push(player_data);
-        }
-        teams_data.push(team_data);
-    }
-
-    Ok(teams_data)
-}
-
-async fn apply_ratings(
-    trans: &mut Transaction<'static, Postgres>,
-    teams_data: &Vec<Vec<PlayerData>>,
-) -> Result<()> {
-    for team in teams_data {
-        for player in team {
-            sqlx::query(
-                "UPDATE lb_players \
-                SET rating = $1, rating_deviation = $2, display_rating = $3, last_updated = $4 \
-                WHERE id = $5",
-            )
-            .bind(player.rating)
-            .bind(player.rating_deviation)
-            .bind(player.display_rating)
-            .bind(player.last_updated)
-            .bind(player.id)
-            .execute(&mut **trans)
-            .await?;
-        }
-    }
-
-    Ok(())
-}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    apply_score_margin, glicko2, load_games, openskill, unrated_player, whr, GameRecord,
+    Leaderboard, LeaderboardSettings, PlayerData, RatingAlgorithm, Standings,
+};
+
+///Number of players listed for each map when map_ratings is set.
+const MAP_TOP_PLAYERS: usize = 3;
+
+///A player looked up by bonk name, by a linked Discord account or by lb_players id.
+#[derive(Clone, Debug)]
+pub enum PlayerQuery {
+    Name(String),
+    Discord(u64),
+    Id(i64),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameResult {
+    Win,
+    Loss,
+    Draw,
+}
+
+///A game from the perspective of one player.
+#[derive(Clone, Debug)]
+pub struct PlayerGame {
+    pub game: GameRecord,
+    pub team: usize,
+    pub result: GameResult,
+    pub old_rating: f64,
+    pub new_rating: f64,
+    pub new_deviation: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayerProfile {
+    pub leaderboard: String,
+    pub player: PlayerData,
+    pub rank: usize,
+    pub player_num: usize,
+    ///Every game of the player from oldest to newest.
+    pub games: Vec<PlayerGame>,
+    ///Names of every player on the leaderboard by id.
+    pub names: HashMap<i64, String>,
+}
+
+///A game where two players were on opposing teams.
+#[derive(Clone, Debug)]
+pub struct Meeting {
+    pub game_id: i64,
+    pub day: sqlx::types::time::Date,
+    ///Result for the first player.
+    pub result: GameResult,
+    ///The first player's chance to win before the game based on the ratings and deviations
+    ///both players had then.
+    pub win_probability: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct HeadToHead {
+    pub leaderboard: String,
+    pub a: PlayerData,
+    pub b: PlayerData,
+    ///Meetings from oldest to newest.
+    pub meetings: Vec<Meeting>,
+    ///The first player's current chance to win.
+    pub win_probability: f64,
+}
+
+///How often a map was played and struck and which side won on it.
+#[derive(Clone, Debug)]
+pub struct MapStats {
+    pub id: i64,
+    pub name: String,
+    pub author: String,
+    pub played: usize,
+    pub struck: usize,
+    ///Wins of each side in games with a single winner and known sides.
+    pub side_wins: Vec<usize>,
+    ///Best rated players on the map by name and display rating when map_ratings is set.
+    pub top_players: Vec<(String, f64)>,
+}
+
+impl MapStats {
+    ///Fraction of decided games won by each side.
+    pub fn side_win_rates(&self) -> Vec<f64> {
+        let decided: usize = self.side_wins.iter().sum();
+        self.side_wins
+            .iter()
+            .map(|wins| *wins as f64 / decided.max(1) as f64)
+            .collect()
+    }
+}
+
+impl HeadToHead {
+    pub fn count(&self, result: GameResult) -> usize {
+        self.meetings
+            .iter()
+            .filter(|meeting| meeting.result == result)
+            .count()
+    }
+}
+
+impl PlayerProfile {
+    pub fn count(&self, result: GameResult) -> usize {
+        self.games
+            .iter()
+            .filter(|game| game.result == result)
+            .count()
+    }
+}
+
+impl PlayerGame {
+    ///Names of every player that wasn't on the player's team.
+    pub fn opponents(&self, names: &HashMap<i64, String>) -> Vec<String> {
+        self.game
+            .teams
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.team)
+            .flat_map(|(_, team)| team)
+            .map(|id| names.get(id).cloned().unwrap_or_default())
+            .collect()
+    }
+
+    ///The player's team score followed by the other teams' scores.
+    pub fn score_string(&self) -> Option<String> {
+        let score = self.game.score.as_ref()?;
+        let own = score.get(self.team)?;
+        let others = score
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.team)
+            .map(|(_, score)| score.to_string());
+
+        Some(
+            std::iter::once(own.to_string())
+                .chain(others)
+                .collect::<Vec<String>>()
+                .join(" - "),
+        )
+    }
+}
+
+impl GameResult {
+    pub fn letter(&self) -> &'static str {
+        match self {
+            GameResult::Win => "W",
+            GameResult::Loss => "L",
+            GameResult::Draw => "D",
+        }
+    }
+}
+
+///Finds a player on the leaderboard along with their rank and the number of ranked players.
+///Provisional players get the rank they would have if they were ranked.
+pub async fn find_player(
+    lb: &Leaderboard,
+    query: &PlayerQuery,
+) -> Result<(PlayerData, usize, usize)> {
+    let Standings {
+        ranked,
+        provisional,
+        inactive,
+    } = lb.standings().await?;
+
+    let lb_player_id: Option<i64> = match query {
+        PlayerQuery::Name(name) => {
+            let query = sqlx::query_scalar(
+                "SELECT l.id FROM lb_players l JOIN player_names n ON n.player_id = l.player_id \
+                WHERE n.name = $1 AND l.lb_id = $2",
+            );
+            query
+                .bind(name)
+                .bind(lb.id)
+                .fetch_optional(lb.db.as_ref())
+                .await?
+        }
+        PlayerQuery::Discord(discord_id) => {
+            let query = sqlx::query_scalar(
+                "SELECT l.id FROM lb_players l JOIN players p ON p.id = l.player_id \
+                WHERE p.discord_id = $1 AND l.lb_id = $2",
+            );
+            query
+                .bind(*discord_id as i64)
+                .bind(lb.id)
+                .fetch_optional(lb.db.as_ref())
+                .await?
+        }
+        PlayerQuery::Id(id) => Some(*id),
+    };
+
+    let player = ranked
+        .iter()
+        .chain(&provisional)
+        .chain(&inactive)
+        .find(|player| {
+            Some(player.id) == lb_player_id
+                || matches!(query, PlayerQuery::Name(name) if *name == player.name)
+        })
+        .cloned()
+        .ok_or(anyhow!("Unable to find player."))?;
+    let rank = ranked
+        .iter()
+        .filter(|other| other.display_rating > player.display_rating)
+        .count()
+        + 1;
+
+    Ok((player, rank, ranked.len()))
+}
+
+///Splits a player's games out of a list of games.
+pub fn player_games(games: Vec<GameRecord>, player_id: i64) -> Vec<PlayerGame> {
+    games
+        .into_iter()
+        .filter_map(|game| {
+            let team = game.team_of(player_id)?;
+            let idx = game.teams[team].iter().position(|id| *id == player_id)?;
+            let placements = game.placements();
+            let result = if placements[team] != 0 {
+                GameResult::Loss
+            } else if placements.iter().filter(|p| **p == 0).count() > 1 {
+                GameResult::Draw
+            } else {
+                GameResult::Win
+            };
+
+            Some(PlayerGame {
+                team,
+                result,
+                old_rating: game.old_rating[team].get(idx).copied().unwrap_or_default(),
+                new_rating: game.new_rating[team].get(idx).copied().unwrap_or_default(),
+                new_deviation: game
+                    .new_deviation
+                    .get(team)
+                    .and_then(|deviations| deviations.get(idx))
+                    .copied(),
+                game,
+            })
+        })
+        .collect()
+}
+
+pub async fn profile(lb: &Leaderboard, query: &PlayerQuery) -> Result<PlayerProfile> {
+    let (player, rank, player_num) = find_player(lb, query).await?;
+
+    let games = load_games(lb.db.as_ref(), lb.id, Some(player.id)).await?;
+    let names: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, name FROM lb_players WHERE lb_id = $1")
+            .bind(lb.id)
+            .fetch_all(lb.db.as_ref())
+            .await?;
+
+    Ok(PlayerProfile {
+        leaderboard: lb.settings.name.clone(),
+        games: player_games(games, player.id),
+        player,
+        rank,
+        player_num,
+        names: names.into_iter().collect(),
+    })
+}
+
+pub async fn head_to_head(
+    lb: &Leaderboard,
+    a: &PlayerQuery,
+    b: &PlayerQuery,
+) -> Result<HeadToHead> {
+    let (a, _, _) = find_player(lb, a).await?;
+    let (b, _, _) = find_player(lb, b).await?;
+    if a.id == b.id {
+        return Err(anyhow!("Those are the same player."));
+    }
+
+    let games = load_games(lb.db.as_ref(), lb.id, Some(a.id)).await?;
+    let a_ratings = pre_game_ratings(&lb.settings, games.clone(), a.id);
+    let b_ratings = pre_game_ratings(
+        &lb.settings,
+        load_games(lb.db.as_ref(), lb.id, Some(b.id)).await?,
+        b.id,
+    );
+    let meetings = games
+        .iter()
+        .filter_map(|game| {
+            let a_team = game.team_of(a.id)?;
+            let b_team = game.team_of(b.id)?;
+            if a_team == b_team {
+                return None;
+            }
+
+            let placements = game.placements();
+            let result = match placements[a_team].cmp(&placements[b_team]) {
+                std::cmp::Ordering::Less => GameResult::Win,
+                std::cmp::Ordering::Equal => GameResult::Draw,
+                std::cmp::Ordering::Greater => GameResult::Loss,
+            };
+
+            Some(Meeting {
+                game_id: game.id,
+                day: game.day,
+                result,
+                win_probability: win_probability(
+                    &lb.settings,
+                    *a_ratings.get(&game.id)?,
+                    *b_ratings.get(&game.id)?,
+                ),
+            })
+        })
+        .collect();
+
+    Ok(HeadToHead {
+        leaderboard: lb.settings.name.clone(),
+        win_probability: win_probability(
+            &lb.settings,
+            (a.rating, a.rating_deviation),
+            (b.rating, b.rating_deviation),
+        ),
+        a,
+        b,
+        meetings,
+    })
+}
+
+///The rating and rating deviation of a player before each of their games by game id. Stored
+///ratings are display ratings, so the deviation from the player's previous game is added back.
+fn pre_game_ratings(
+    settings: &LeaderboardSettings,
+    games: Vec<GameRecord>,
+    player_id: i64,
+) -> HashMap<i64, (f64, f64)> {
+    let cre = settings.cre.unwrap_or(0.);
+    let mut deviation = settings.unrated_deviation * settings.rating_scale;
+    player_games(games, player_id)
+        .into_iter()
+        .map(|game| {
+            let rating = (game.old_rating + deviation * cre, deviation);
+            deviation = game.new_deviation.unwrap_or(deviation);
+            (game.game.id, rating)
+        })
+        .collect()
+}
+
+///The chance that the first player beats the second in a 1v1 with the leaderboard's
+///rating model. Players are (rating, rating deviation).
+pub fn win_probability(settings: &LeaderboardSettings, a: (f64, f64), b: (f64, f64)) -> f64 {
+    let scale = settings.rating_scale;
+    let diff = a.0 - b.0;
+
+    match settings.algorithm {
+        RatingAlgorithm::OpenSkill => {
+            let c = (a.1.powi(2) + b.1.powi(2) + 2. * scale.powi(2)).sqrt();
+            1. / (1. + (-diff / c).exp())
+        }
+        RatingAlgorithm::Glicko2 => {
+            let phi_2 = (a.1.powi(2) + b.1.powi(2)) / scale.powi(2);
+            let g = 1. / (1. + 3. * phi_2 / std::f64::consts::PI.powi(2)).sqrt();
+            1. / (1. + (-g * diff / scale).exp())
+        }
+        RatingAlgorithm::WHR => 1. / (1. + (-diff / scale).exp()),
+    }
+}
+
+///Draws ratings as a line of block characters.
+pub fn sparkline(ratings: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = ratings.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = ratings.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    ratings
+        .iter()
+        .map(|rating| {
+            let level = if max > min {
+                (rating - min) / (max - min) * (BLOCKS.len() - 1) as f64
+            } else {
+                0.
+            };
+            BLOCKS[level.round() as usize]
+        })
+        .collect()
+}
+
+///Stats for every map that was played or struck on the leaderboard, most played first.
+pub async fn map_stats(lb: &Leaderboard) -> Result<Vec<MapStats>> {
+    let maps: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT id, name, author FROM maps WHERE id IN \
+        (SELECT map_id FROM lb_games WHERE lb_id = $1 \
+        UNION SELECT UNNEST(struck_maps) FROM lb_games WHERE lb_id = $1)",
+    )
+    .bind(lb.id)
+    .fetch_all(lb.db.as_ref())
+    .await?;
+    let strikes: HashMap<i64, i64> = sqlx::query_as(
+        "SELECT map_id, COUNT(*) FROM lb_games, UNNEST(struck_maps) AS map_id \
+        WHERE lb_id = $1 GROUP BY map_id",
+    )
+    .bind(lb.id)
+    .fetch_all(lb.db.as_ref())
+    .await?
+    .into_iter()
+    .collect();
+    let games = load_games(lb.db.as_ref(), lb.id, None).await?;
+    let names: HashMap<i64, String> =
+        sqlx::query_as("SELECT id, name FROM lb_players WHERE lb_id = $1")
+            .bind(lb.id)
+            .fetch_all(lb.db.as_ref())
+            .await?
+            .into_iter()
+            .collect();
+
+    let mut stats: Vec<MapStats> = maps
+        .into_iter()
+        .map(|(id, name, author)| {
+            let map_games: Vec<GameRecord> = games
+                .iter()
+                .filter(|game| game.map_id == Some(id))
+                .cloned()
+                .collect();
+
+            let mut side_wins: Vec<usize> = vec![];
+            for game in &map_games {
+                let placements = game.placements();
+                if placements.iter().filter(|p| **p == 0).count() != 1 {
+                    continue;
+                }
+                let Some(Some(side)) = game.sides.first() else {
+                    continue;
+                };
+                let side = *side as usize;
+                if side_wins.len() <= side {
+                    side_wins.resize(side + 1, 0);
+                }
+                side_wins[side] += 1;
+            }
+
+            let top_players = if lb.settings.map_ratings == Some(true) {
+                let mut players = map_ratings(&lb.settings, &map_games, &names);
+                players.truncate(MAP_TOP_PLAYERS);
+                players
+            } else {
+                vec![]
+            };
+
+            MapStats {
+                id,
+                name,
+                author,
+                played: map_games.len(),
+                struck: strikes.get(&id).copied().unwrap_or(0) as usize,
+                side_wins,
+                top_players,
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| b.played.cmp(&a.played).then(b.struck.cmp(&a.struck)));
+
+    Ok(stats)
+}
+
+///Ratings from only the games played on one map, sorted by display rating.
+///Every player starts unrated and seasons are ignored.
+fn map_ratings(
+    settings: &LeaderboardSettings,
+    games: &[GameRecord],
+    names: &HashMap<i64, String>,
+) -> Vec<(String, f64)> {
+    let name = |id: &i64| names.get(id).cloned().unwrap_or_default();
+    let cre = settings.cre.unwrap_or(0.);
+
+    let mut ratings: Vec<(String, f64)> = match settings.algorithm {
+        RatingAlgorithm::WHR => whr::whr(settings, games)
+            .iter()
+            .filter_map(|(id, days)| {
+                let day = days.last()?;
+                Some((name(id), day.rating - day.rating_deviation * cre))
+            })
+            .collect(),
+        RatingAlgorithm::OpenSkill | RatingAlgorithm::Glicko2 => {
+            let mut players: HashMap<i64, PlayerData> = HashMap::new();
+            for game in games {
+                let mut teams_data: Vec<Vec<PlayerData>> = game
+                    .teams
+                    .iter()
+                    .map(|team| {
+                        team.iter()
+                            .map(|id| {
+                                players.get(id).cloned().unwrap_or_else(|| {
+                                    unrated_player(settings, *id, name(id), game.day)
+                                })
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                let before = teams_data.clone();
+                if let RatingAlgorithm::OpenSkill = settings.algorithm {
+                    openskill::reverse_pl(settings, &game.ties, &mut teams_data, game.day);
+                } else {
+                    glicko2::glicko2(settings, &game.ties, &mut teams_data, game.day);
+                }
+                apply_score_margin(settings, game.score.as_ref(), &before, &mut teams_data);
+
+                for player in teams_data.into_iter().flatten() {
+                    players.insert(player.id, player);
+                }
+            }
+
+            players
+                .into_values()
+                .map(|player| (player.name, player.display_rating))
+                .collect()
+        }
+    };
+    ratings.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ratings
+}
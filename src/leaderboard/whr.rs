@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use sqlx::{
+    types::time::{Date, OffsetDateTime},
+    Postgres, Transaction,
+};
+
+use crate::leaderboard::Leaderboard;
+
+use super::{
+    get_teams, last_hard_reset, load_games, load_seasons, GameRecord, LeaderboardSettings, NewGame,
+};
+
+const MAX_ITERATIONS: usize = 100;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+//Keeps the rating system solvable when deviation_per_day or unrated_deviation is 0.
+const MIN_VARIANCE: f64 = 0.000001;
+
+#[derive(Clone, Debug)]
+pub struct DayRating {
+    pub day: Date,
+    pub rating: f64,
+    pub rating_deviation: f64,
+}
+
+///Update ratings with Whole-History Rating. Every player's rating history is recomputed.
+///The score is saved but doesn't affect ratings.
+pub async fn update(lb: &mut Leaderboard, game: NewGame) -> Result<String> {
+    let mut trans = lb.db.begin().await?;
+
+    let mut teams_data = get_teams(lb, &mut trans, game.teams.clone()).await?;
+
+    let today = OffsetDateTime::now_utc().date();
+
+    //Games before the last hard reset don't count toward the current ratings.
+    let seasons = load_seasons(&mut *trans, lb.id).await?;
+    let hard_reset = last_hard_reset(&seasons);
+    let mut games = load_games(&mut *trans, lb.id, None).await?;
+    games.retain(|game| game.season_num >= hard_reset);
+    games.push(GameRecord {
+        id: 0,
+        season_num: lb.season,
+        day: today,
+        score: game.score.clone(),
+        ties: game.ties.clone(),
+        map_id: game.map.as_ref().map(|map| map.id),
+        teams: teams_data
+            .iter()
+            .map(|team| team.iter().map(|player| player.id).collect())
+            .collect(),
+        old_rating: vec![],
+        new_rating: vec![],
+        new_deviation: vec![],
+        sides: game.sides.clone(),
+    });
+
+    let ratings = whr(&lb.settings, &games);
+    save_ratings(lb, &mut trans, &ratings).await?;
+
+    for team in &mut teams_data {
+        for player in team {
+            if let Some(day) = ratings.get(&player.id).and_then(|days| days.last()) {
+                player.rating = day.rating;
+                player.rating_deviation = day.rating_deviation;
+                player.display_rating =
+                    player.rating - player.rating_deviation * lb.settings.cre.unwrap_or(0.);
+                player.last_updated = day.day;
+            }
+        }
+    }
+
+    let match_string = lb.save_game(&mut trans, &teams_data, today, &game).await?;
+
+    trans.commit().await?;
+
+    Ok(match_string)
+}
+
+///Replaces the stored daily ratings and sets each player's rating to their most recent day.
+pub async fn save_ratings(
+    lb: &Leaderboard,
+    trans: &mut Transaction<'static, Postgres>,
+    ratings: &HashMap<i64, Vec<DayRating>>,
+) -> Result<()> {
+    sqlx::query(
+        "DELETE FROM lb_player_days_whr \
+        WHERE lb_player_id IN (SELECT id FROM lb_players WHERE lb_id = $1)",
+    )
+    .bind(lb.id)
+    .execute(&mut **trans)
+    .await?;
+
+    let mut player_ids: Vec<i64> = vec![];
+    let mut days: Vec<Date> = vec![];
+    let mut day_ratings: Vec<f64> = vec![];
+    let mut day_deviations: Vec<f64> = vec![];
+    for (player_id, player_days) in ratings {
+        for day in player_days {
+            player_ids.push(*player_id);
+            days.push(day.day);
+            day_ratings.push(day.rating);
+            day_deviations.push(day.rating_deviation);
+        }
+
+        let Some(last_day) = player_days.last() else {
+            continue;
+        };
+        sqlx::query(
+            "UPDATE lb_players \
+            SET rating = $1, rating_deviation = $2, display_rating = $3, last_updated = $4 \
+            WHERE id = $5",
+        )
+        .bind(last_day.rating)
+        .bind(last_day.rating_deviation)
+        .bind(last_day.rating - last_day.rating_deviation * lb.settings.cre.unwrap_or(0.))
+        .bind(last_day.day)
+        .bind(player_id)
+        .execute(&mut **trans)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO lb_player_days_whr (lb_player_id, day, rating, rating_deviation) \
+        SELECT * FROM UNNEST($1::BIGINT[], $2::DATE[], $3::DOUBLE PRECISION[], $4::DOUBLE PRECISION[])",
+    )
+    .bind(player_ids)
+    .bind(days)
+    .bind(day_ratings)
+    .bind(day_deviations)
+    .execute(&mut **trans)
+    .await?;
+
+    Ok(())
+}
+
+///Whole-History Rating (Coulom, 2008) for every player in a list of games.
+///A player's first day has a prior of unrated_deviation around the mean rating and
+///their rating drifts by deviation_per_day per day after that. A team's rating is the
+///sum of its players' ratings and every pair of teams in a game counts as a result.
+pub fn whr(settings: &LeaderboardSettings, games: &[GameRecord]) -> HashMap<i64, Vec<DayRating>> {
+    //(player index, day index) of every player on every team.
+    struct Game {
+        teams: Vec<Vec<(usize, usize)>>,
+        placements: Vec<usize>,
+    }
+    struct Timeline {
+        id: i64,
+        days: Vec<Date>,
+        ratings: Vec<f64>,
+        //(game index, team index, day index)
+        games: Vec<(usize, usize, usize)>,
+    }
+
+    let mut timelines: Vec<Timeline> = vec![];
+    let mut timeline_idx: HashMap<i64, usize> = HashMap::new();
+    for game in games {
+        for team in &game.teams {
+            for player_id in team {
+                let idx = *timeline_idx.entry(*player_id).or_insert_with(|| {
+                    timelines.push(Timeline {
+                        id: *player_id,
+                        days: vec![],
+                        ratings: vec![],
+                        games: vec![],
+                    });
+                    timelines.len() - 1
+                });
+                timelines[idx].days.push(game.day);
+            }
+        }
+    }
+    for timeline in &mut timelines {
+        timeline.days.sort();
+        timeline.days.dedup();
+        timeline.ratings = vec![0.; timeline.days.len()];
+    }
+
+    let mut whr_games: Vec<Game> = vec![];
+    for (game_idx, game) in games.iter().enumerate() {
+        let placements = game.placements();
+
+        let mut teams = vec![];
+        for (team_idx, team) in game.teams.iter().enumerate() {
+            let mut whr_team = vec![];
+            for player_id in team {
+                let Some(&idx) = timeline_idx.get(player_id) else {
+                    continue;
+                };
+                let timeline = &mut timelines[idx];
+                let Ok(day_idx) = timeline.days.binary_search(&game.day) else {
+                    continue;
+                };
+                timeline.games.push((game_idx, team_idx, day_idx));
+                whr_team.push((idx, day_idx));
+            }
+            teams.push(whr_team);
+        }
+
+        whr_games.push(Game { teams, placements });
+    }
+
+    let prior_variance = settings.unrated_deviation.powi(2).max(MIN_VARIANCE);
+    let day_variance = settings.deviation_per_day.powi(2).max(MIN_VARIANCE);
+
+    //Gradient of the log likelihood along with the diagonal and off-diagonal
+    //of the negative Hessian for one player.
+    let system = |timelines: &Vec<Timeline>, idx: usize| {
+        let timeline = &timelines[idx];
+        let len = timeline.days.len();
+        let mut gradient = vec![0.; len];
+        let mut diagonal = vec![0.; len];
+        let mut off_diagonal = vec![0.; len.saturating_sub(1)];
+
+        gradient[0] -= timeline.ratings[0] / prior_variance;
+        diagonal[0] += 1. / prior_variance;
+
+        for k in 0..len.saturating_sub(1) {
+            let days = timeline.days[k + 1].to_julian_day() - timeline.days[k].to_julian_day();
+            let c = 1. / (day_variance * f64::from(days));
+            let diff = timeline.ratings[k + 1] - timeline.ratings[k];
+            gradient[k] += diff * c;
+            gradient[k + 1] -= diff * c;
+            diagonal[k] += c;
+            diagonal[k + 1] += c;
+            off_diagonal[k] = -c;
+        }
+
+        for (game_idx, team_idx, day_idx) in &timeline.games {
+            let game = &whr_games[*game_idx];
+            let team_ratings: Vec<f64> = game
+                .teams
+                .iter()
+                .map(|team| team.iter().map(|(p, d)| timelines[*p].ratings[*d]).sum())
+                .collect();
+            let weight = 1. / (game.teams.len().max(2) - 1) as f64;
+
+            for (other_idx, other_rating) in team_ratings.iter().enumerate() {
+                if other_idx == *team_idx {
+                    continue;
+                }
+                let score = match game.placements[*team_idx].cmp(&game.placements[other_idx]) {
+                    std::cmp::Ordering::Less => 1.,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.,
+                };
+                let p = 1. / (1. + (other_rating - team_ratings[*team_idx]).exp());
+                gradient[*day_idx] += weight * (score - p);
+                diagonal[*day_idx] += weight * p * (1. - p);
+            }
+        }
+
+        (gradient, diagonal, off_diagonal)
+    };
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut max_change: f64 = 0.;
+        for idx in 0..timelines.len() {
+            let (gradient, diagonal, off_diagonal) = system(&timelines, idx);
+            let step = solve_tridiagonal(&diagonal, &off_diagonal, &gradient);
+            for (rating, change) in timelines[idx].ratings.iter_mut().zip(step) {
+                *rating += change;
+                max_change = max_change.max(change.abs());
+            }
+        }
+        if max_change < CONVERGENCE_TOLERANCE {
+            break;
+        }
+    }
+
+    let mut output = HashMap::new();
+    for idx in 0..timelines.len() {
+        let (_, diagonal, off_diagonal) = system(&timelines, idx);
+        let variances = tridiagonal_inverse_diagonal(&diagonal, &off_diagonal);
+        let timeline = &timelines[idx];
+
+        output.insert(
+            timeline.id,
+            timeline
+                .days
+                .iter()
+                .zip(&timeline.ratings)
+                .zip(variances)
+                .map(|((day, rating), variance)| DayRating {
+                    day: *day,
+                    rating: settings.mean_rating + rating * settings.rating_scale,
+                    rating_deviation: variance.max(0.).sqrt() * settings.rating_scale,
+                })
+                .collect(),
+        );
+    }
+
+    output
+}
+
+///Solves a symmetric tridiagonal system with the Thomas algorithm.
+fn solve_tridiagonal(diagonal: &[f64], off_diagonal: &[f64], rhs: &[f64]) -> Vec<f64> {
+    let len = diagonal.len();
+    let mut c = vec![0.; len];
+    let mut d = vec![0.; len];
+    for i in 0..len {
+        let (prev_c, prev_d, prev_off) = if i > 0 {
+            (c[i - 1], d[i - 1], off_diagonal[i - 1])
+        } else {
+            (0., 0., 0.)
+        };
+        let m = diagonal[i] - prev_off * prev_c;
+        c[i] = off_diagonal.get(i).copied().unwrap_or(0.) / m;
+        d[i] = (rhs[i] - prev_off * prev_d) / m;
+    }
+
+    let mut x = vec![0.; len];
+    for i in (0..len).rev() {
+        x[i] = d[i] - c[i] * x.get(i + 1).copied().unwrap_or(0.);
+    }
+    x
+}
+
+///Diagonal of the inverse of a symmetric tridiagonal matrix.
+fn tridiagonal_inverse_diagonal(diagonal: &[f64], off_diagonal: &[f64]) -> Vec<f64> {
+    let len = diagonal.len();
+    let mut forward = vec![0.; len];
+    let mut backward = vec![0.; len];
+    for i in 0..len {
+        forward[i] = diagonal[i];
+        if i > 0 {
+            forward[i] -= off_diagonal[i - 1].powi(2) / forward[i - 1];
+        }
+    }
+    for i in (0..len).rev() {
+        backward[i] = diagonal[i];
+        if i + 1 < len {
+            backward[i] -= off_diagonal[i].powi(2) / backward[i + 1];
+        }
+    }
+
+    (0..len)
+        .map(|i| 1. / (forward[i] + backward[i] - diagonal[i]))
+        .collect()
+}
@@ -1,6 +1,11 @@
 mod bonk_bot;
+mod challenges;
 mod discord_commands;
 mod leaderboard;
+mod maps;
+mod matchmaking;
+mod players;
+mod tournament;
 
 #[cfg(test)]
 mod tests;
@@ -10,6 +15,7 @@ use std::sync::Arc;
 use anyhow::{Context, Result};
 use bonk_bot::{BonkBotKey, BonkBotValue};
 use dotenv;
+use matchmaking::{Matchmaker, MatchmakingKey};
 use serenity::{
     all::{
         ActivityData, Command, CommandInteraction, CreateCommand, CreateCommandOption,
@@ -19,6 +25,7 @@ use serenity::{
     async_trait,
     prelude::TypeMapKey,
 };
+use tournament::{TournamentKey, TournamentRunner};
 
 struct Handler;
 
@@ -82,8 +89,15 @@ impl EventHandler for Handler {
         }
 
         let mut data = ctx.data.write().await;
+        //Ready fires again on every reconnect, so background tasks are only started once.
+        let first_ready = !data.contains_key::<DatabaseKey>();
 
         data.insert::<BonkBotKey>(BonkBotValue::new().await);
+        if first_ready {
+            data.insert::<TournamentKey>(TournamentRunner::start(ctx.clone()));
+            //The matchmaking queue is only kept in memory and would be lost on a restart.
+            data.insert::<MatchmakingKey>(Matchmaker::start(ctx.clone()));
+        }
 
         let db = sqlx::postgres::PgPool::connect(
             &dotenv::var("DATABASE_URL").expect("Missing database URL."),
@@ -98,10 +112,22 @@ impl EventHandler for Handler {
         };
 
         data.insert::<DatabaseKey>(DatabaseValue { db: Arc::new(db) });
+        if first_ready {
+            challenges::start(ctx.clone());
+        }
     }
 
     async fn interaction_create(&self, ctx: serenity::all::Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
+        if let Interaction::Component(component) = interaction {
+            if let Err(e) = discord_commands::component(&ctx, &component).await {
+                let message = CreateInteractionResponseMessage::new()
+                    .content(format!("Command failed: {e}"))
+                    .ephemeral(true);
+                let _ = component
+                    .create_response(&ctx.http, CreateInteractionResponse::Message(message))
+                    .await;
+            }
+        } else if let Interaction::Command(command) = interaction {
             let res: Result<()> = async {
                 if command.data.name == "elo" {
                     let args = &command.data.options;
@@ -182,6 +208,26 @@ async fn parse_command(
             match *subcommand {
                 "help" | "h" | "?" => discord_commands::help(ctx, interaction).await?,
                 "ping" => discord_commands::ping(ctx, interaction, args).await?,
+                "link" => discord_commands::link(ctx, interaction, args).await?,
+                "tournament" | "t" => discord_commands::tournament(ctx, interaction, args).await?,
+                "queue" | "q" => discord_commands::queue(ctx, interaction, args).await?,
+                "rating" | "r" => {
+                    discord_commands::player_commands::rating(ctx, interaction, args).await?
+                }
+                "profile" => {
+                    discord_commands::player_commands::profile(ctx, interaction, args).await?
+                }
+                "history" => {
+                    discord_commands::player_commands::history(ctx, interaction, args).await?
+                }
+                "h2h" => discord_commands::player_commands::h2h(ctx, interaction, args).await?,
+                "graph" => discord_commands::player_commands::graph(ctx, interaction, args).await?,
+                "mapstats" => {
+                    discord_commands::player_commands::mapstats(ctx, interaction, args).await?
+                }
+                "challenge" => {
+                    discord_commands::player_commands::challenge(ctx, interaction, args).await?
+                }
                 "a" => discord_commands::a(ctx, interaction, args).await?,
                 _ => {
                     let message = CreateInteractionResponseMessage::new()
@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+
+use crate::leaderboard::GameMap;
+
+const DISCORD_CHARACTER_LIMIT: usize = 2000;
+
+///Adds maps from raw map data to the library. The data can be one map or a list of maps
+///like the output of sgrAPI.getFav(0). Maps that are already in the library are updated.
+pub async fn add(db: &Pool<Postgres>, data: &str, pool: Option<&str>) -> Result<String> {
+    let value: Value = serde_json::from_str(data).context("Invalid map data.")?;
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+
+    let mut trans = db.begin().await?;
+    if let Some(pool) = pool {
+        find_pool(&mut trans, pool).await?;
+    }
+
+    let mut added = vec![];
+    for value in values {
+        let map: GameMap = serde_json::from_value(value.clone()).context("Invalid map data.")?;
+        sqlx::query(
+            "INSERT INTO maps (id, name, author, data) VALUES ($1, $2, $3, $4) \
+            ON CONFLICT (id) DO UPDATE SET name = $2, author = $3, data = $4",
+        )
+        .bind(map.id)
+        .bind(&map.name)
+        .bind(&map.author)
+        .bind(value.to_string())
+        .execute(&mut *trans)
+        .await?;
+
+        if let Some(pool) = pool {
+            add_to_pool(&mut trans, pool, map.id).await?;
+        }
+        added.push(map_string(&map));
+    }
+
+    trans.commit().await?;
+
+    Ok(format!(
+        "Added {} map{}{}:\n{}",
+        added.len(),
+        if added.len() == 1 { "" } else { "s" },
+        pool.map(|pool| format!(" to {}", pool)).unwrap_or_default(),
+        added.join("\n")
+    ))
+}
+
+///Removes a map from the library and every pool. Stats from games on the map are kept.
+pub async fn remove(db: &Pool<Postgres>, id: i64) -> Result<String> {
+    let mut trans = db.begin().await?;
+
+    let map: Option<(String, String)> = sqlx::query_as(
+        "UPDATE maps SET data = NULL WHERE id = $1 AND data IS NOT NULL RETURNING name, author",
+    )
+    .bind(id)
+    .fetch_optional(&mut *trans)
+    .await?;
+    let (name, author) = map.context("That map isn't in the library.")?;
+    sqlx::query("DELETE FROM map_pool_maps WHERE map_id = $1")
+        .bind(id)
+        .execute(&mut *trans)
+        .await?;
+
+    trans.commit().await?;
+
+    Ok(format!("Removed {} by {} (#{}).", name, author, id))
+}
+
+///Lists every map in the library or in a pool.
+pub async fn list(db: &Pool<Postgres>, pool: Option<&str>) -> Result<String> {
+    let maps: Vec<(i64, String, String)> = match pool {
+        Some(pool) => {
+            let mut trans = db.begin().await?;
+            find_pool(&mut trans, pool).await?;
+            sqlx::query_as(
+                "SELECT m.id, m.name, m.author FROM map_pool_maps p JOIN maps m ON m.id = p.map_id \
+                WHERE p.pool = $1 ORDER BY p.position",
+            )
+            .bind(pool)
+            .fetch_all(&mut *trans)
+            .await?
+        }
+        None => {
+            sqlx::query_as("SELECT id, name, author FROM maps WHERE data IS NOT NULL ORDER BY name")
+                .fetch_all(db)
+                .await?
+        }
+    };
+
+    let title = match pool {
+        Some(pool) => format!("Maps in {}", pool),
+        None => "Map library".to_string(),
+    };
+    if maps.is_empty() {
+        return Ok(format!("{}:\n\nNo maps.", title));
+    }
+
+    let mut output = format!("{}:\n", title);
+    for (id, name, author) in maps {
+        let map_str = format!("\n{}", map_string(&GameMap { id, name, author }));
+        //Leaves room for the ellipsis.
+        if output.encode_utf16().count() + map_str.encode_utf16().count()
+            > DISCORD_CHARACTER_LIMIT - 4
+        {
+            output.push_str("\n...");
+            break;
+        }
+        output.push_str(&map_str);
+    }
+
+    Ok(output)
+}
+
+pub async fn create_pool(db: &Pool<Postgres>, name: &str) -> Result<String> {
+    let created = sqlx::query("INSERT INTO map_pools (name) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(name)
+        .execute(db)
+        .await?;
+    if created.rows_affected() == 0 {
+        return Err(anyhow!("A map pool named {} already exists.", name));
+    }
+
+    Ok(format!("Created map pool {}.", name))
+}
+
+pub async fn delete_pool(db: &Pool<Postgres>, name: &str) -> Result<String> {
+    let deleted = sqlx::query("DELETE FROM map_pools WHERE name = $1")
+        .bind(name)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(anyhow!("Unable to find map pool {}.", name));
+    }
+
+    Ok(format!("Deleted map pool {}.", name))
+}
+
+///Adds maps from the library to a pool.
+pub async fn add_pool_maps(db: &Pool<Postgres>, name: &str, ids: &[i64]) -> Result<String> {
+    let mut trans = db.begin().await?;
+    find_pool(&mut trans, name).await?;
+
+    for id in ids {
+        let in_library: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM maps WHERE id = $1 AND data IS NOT NULL)",
+        )
+        .bind(id)
+        .fetch_one(&mut *trans)
+        .await?;
+        if !in_library {
+            return Err(anyhow!("Map #{} isn't in the library.", id));
+        }
+        add_to_pool(&mut trans, name, *id).await?;
+    }
+
+    trans.commit().await?;
+
+    Ok(format!("Added {} map(s) to {}.", ids.len(), name))
+}
+
+pub async fn remove_pool_maps(db: &Pool<Postgres>, name: &str, ids: &[i64]) -> Result<String> {
+    let mut trans = db.begin().await?;
+    find_pool(&mut trans, name).await?;
+
+    let removed = sqlx::query("DELETE FROM map_pool_maps WHERE pool = $1 AND map_id = ANY($2)")
+        .bind(name)
+        .bind(ids)
+        .execute(&mut *trans)
+        .await?;
+
+    trans.commit().await?;
+
+    Ok(format!(
+        "Removed {} map(s) from {}.",
+        removed.rows_affected(),
+        name
+    ))
+}
+
+///Lists every map pool with the number of maps in it.
+pub async fn list_pools(db: &Pool<Postgres>) -> Result<String> {
+    let pools: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT p.name, COUNT(m.map_id) FROM map_pools p \
+        LEFT JOIN map_pool_maps m ON m.pool = p.name GROUP BY p.name ORDER BY p.name",
+    )
+    .fetch_all(db)
+    .await?;
+    if pools.is_empty() {
+        return Ok("Map pools:\n\nNo map pools.".to_string());
+    }
+
+    Ok(format!(
+        "Map pools:\n\n{}",
+        pools
+            .iter()
+            .map(|(name, maps)| format!("{} ({} maps)", name, maps))
+            .collect::<Vec<String>>()
+            .join("\n")
+    ))
+}
+
+///Raw map data of every map in a pool for a room config.
+pub async fn pool_maps(db: &Pool<Postgres>, name: &str) -> Result<Vec<String>> {
+    let mut trans = db.begin().await?;
+    find_pool(&mut trans, name).await?;
+
+    let maps: Vec<String> = sqlx::query_scalar(
+        "SELECT m.data FROM map_pool_maps p JOIN maps m ON m.id = p.map_id \
+        WHERE p.pool = $1 AND m.data IS NOT NULL ORDER BY p.position",
+    )
+    .bind(name)
+    .fetch_all(&mut *trans)
+    .await?;
+
+    Ok(maps)
+}
+
+async fn find_pool(trans: &mut sqlx::Transaction<'static, Postgres>, name: &str) -> Result<()> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM map_pools WHERE name = $1)")
+        .bind(name)
+        .fetch_one(&mut **trans)
+        .await?;
+    if !exists {
+        return Err(anyhow!("Unable to find map pool {}.", name));
+    }
+
+    Ok(())
+}
+
+///Adds a map to the end of a pool if it isn't already in it.
+async fn add_to_pool(
+    trans: &mut sqlx::Transaction<'static, Postgres>,
+    name: &str,
+    map_id: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO map_pool_maps (pool, map_id, position) \
+        SELECT $1, $2, COALESCE(MAX(position), 0) + 1 FROM map_pool_maps WHERE pool = $1 \
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(name)
+    .bind(map_id)
+    .execute(&mut **trans)
+    .await?;
+
+    Ok(())
+}
+
+fn map_string(map: &GameMap) -> String {
+    format!("#{} {} by {}", map.id, map.name, map.author)
+}
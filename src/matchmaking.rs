@@ -0,0 +1,311 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serenity::all::ChannelId;
+use serenity::prelude::TypeMapKey;
+use sqlx::{Pool, Postgres};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Instant};
+
+use crate::bonk_bot::room_maker::{Queue, RoomParameters};
+use crate::bonk_bot::BonkBotKey;
+use crate::leaderboard::LeaderboardSettings;
+use crate::players;
+
+const PAIR_INTERVAL: Duration = Duration::from_secs(5);
+///Rating difference allowed when a player joins, in units of the leaderboard's rating scale.
+const BASE_RANGE: f64 = 0.5;
+///How much the allowed rating difference grows every minute spent in the queue.
+const RANGE_PER_MINUTE: f64 = 0.5;
+
+pub struct MatchmakingKey;
+
+impl TypeMapKey for MatchmakingKey {
+    type Value = mpsc::Sender<MatchmakingMessage>;
+}
+
+///buffer 10, blocking send
+pub enum MatchmakingMessage {
+    Join {
+        player: QueuedPlayer,
+        reply: oneshot::Sender<Result<usize>>,
+    },
+    Leave {
+        discord_id: u64,
+        reply: oneshot::Sender<Option<String>>,
+    },
+    List {
+        reply: oneshot::Sender<Vec<(String, usize)>>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct QueuedPlayer {
+    pub discord_id: u64,
+    pub name: String,
+    pub lb: String,
+    pub rating: f64,
+    pub rating_scale: f64,
+    ///Where the room link is sent.
+    pub channel: ChannelId,
+    pub joined: Instant,
+}
+
+impl QueuedPlayer {
+    ///The rating difference this player accepts, which widens the longer they wait.
+    fn range(&self, now: Instant) -> f64 {
+        let minutes = now.duration_since(self.joined).as_secs_f64() / 60.;
+        (BASE_RANGE + RANGE_PER_MINUTE * minutes) * self.rating_scale
+    }
+}
+
+///Keeps the queues of every leaderboard and opens a one game room for each pair of players.
+pub struct Matchmaker {
+    rx: mpsc::Receiver<MatchmakingMessage>,
+    ctx: serenity::all::Context,
+    queue: Vec<QueuedPlayer>,
+}
+
+impl Matchmaker {
+    pub fn start(ctx: serenity::all::Context) -> mpsc::Sender<MatchmakingMessage> {
+        let (tx, rx) = mpsc::channel(10);
+        let mut matchmaker = Matchmaker {
+            rx,
+            ctx,
+            queue: vec![],
+        };
+        tokio::spawn(async move { matchmaker.run().await });
+        tx
+    }
+
+    async fn run(&mut self) {
+        let mut interval = time::interval(PAIR_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                message = self.rx.recv() => {
+                    let Some(message) = message else {
+                        return;
+                    };
+                    self.handle_message(message);
+                }
+                _ = interval.tick() => {}
+            }
+
+            while let Some((a, b)) = find_pair(&self.queue, Instant::now()) {
+                //Remove the later index first so the earlier one stays valid.
+                let second = self.queue.remove(a.max(b));
+                let first = self.queue.remove(a.min(b));
+                let ctx = self.ctx.clone();
+                tokio::spawn(async move { open_match(&ctx, first, second).await });
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: MatchmakingMessage) {
+        match message {
+            MatchmakingMessage::Join { player, reply } => {
+                let result = match self
+                    .queue
+                    .iter()
+                    .find(|queued| queued.discord_id == player.discord_id)
+                {
+                    Some(queued) => Err(anyhow!("You're already in the {} queue.", queued.lb)),
+                    None => {
+                        let lb = player.lb.clone();
+                        self.queue.push(player);
+                        Ok(self.queue.iter().filter(|queued| queued.lb == lb).count())
+                    }
+                };
+                let _ = reply.send(result);
+            }
+            MatchmakingMessage::Leave { discord_id, reply } => {
+                let position = self
+                    .queue
+                    .iter()
+                    .position(|queued| queued.discord_id == discord_id);
+                let _ = reply.send(position.map(|position| self.queue.remove(position).lb));
+            }
+            MatchmakingMessage::List { reply } => {
+                let mut queues: Vec<(String, usize)> = vec![];
+                for queued in &self.queue {
+                    match queues.iter_mut().find(|(lb, _)| *lb == queued.lb) {
+                        Some((_, players)) => *players += 1,
+                        None => queues.push((queued.lb.clone(), 1)),
+                    }
+                }
+                let _ = reply.send(queues);
+            }
+        }
+    }
+}
+
+///Finds the longest waiting player who has an opponent in range and pairs them with the
+///closest rated one. Two players are in range when the difference between their ratings is
+///within the wider of their ranges.
+pub fn find_pair(queue: &[QueuedPlayer], now: Instant) -> Option<(usize, usize)> {
+    let mut order = (0..queue.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|i| queue[*i].joined);
+
+    order.iter().find_map(|&i| {
+        let player = &queue[i];
+        queue
+            .iter()
+            .enumerate()
+            .filter(|(j, other)| *j != i && other.lb == player.lb)
+            .map(|(j, other)| (j, (player.rating - other.rating).abs(), other))
+            .filter(|(_, difference, other)| *difference <= player.range(now).max(other.range(now)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(j, _, _)| (i, j))
+    })
+}
+
+///Adds the player linked to a Discord account to a leaderboard's queue. Returns how many
+///players are waiting in it.
+pub async fn join(
+    ctx: &serenity::all::Context,
+    db: &Pool<Postgres>,
+    lb: &str,
+    discord_id: u64,
+    channel: ChannelId,
+) -> Result<usize> {
+    let (settings, has_room): (serde_json::Value, bool) = sqlx::query_as(
+        "SELECT settings, room IS NOT NULL FROM leaderboard WHERE abbreviation = $1",
+    )
+    .bind(lb)
+    .fetch_optional(db)
+    .await?
+    .context("Leaderboard not found.")?;
+    if !has_room {
+        return Err(anyhow!(
+            "{} doesn't have a room config. An admin can add one with \"a lb room set\".",
+            lb
+        ));
+    }
+    let settings: LeaderboardSettings = serde_json::from_value(settings)?;
+
+    let (name, rating): (String, Option<f64>) = sqlx::query_as(
+        "SELECT p.name, l.display_rating FROM players p \
+        LEFT JOIN lb_players l ON l.player_id = p.id \
+        AND l.lb_id = (SELECT id FROM leaderboard WHERE abbreviation = $2) \
+        WHERE p.discord_id = $1",
+    )
+    .bind(discord_id as i64)
+    .bind(lb)
+    .fetch_optional(db)
+    .await?
+    .context("Link your bonk.io account with \"/elo link\" first.")?;
+    let unrated = settings.mean_rating
+        - settings.unrated_deviation * settings.rating_scale * settings.cre.unwrap_or(0.);
+
+    let (tx, rx) = oneshot::channel();
+    matchmaking_tx(ctx)
+        .await?
+        .send(MatchmakingMessage::Join {
+            player: QueuedPlayer {
+                discord_id,
+                name,
+                lb: lb.to_string(),
+                rating: rating.unwrap_or(unrated),
+                rating_scale: settings.rating_scale,
+                channel,
+                joined: Instant::now(),
+            },
+            reply: tx,
+        })
+        .await?;
+
+    rx.await?
+}
+
+///Takes a Discord account out of its queue. Returns the leaderboard it was queued for.
+pub async fn leave(ctx: &serenity::all::Context, discord_id: u64) -> Result<Option<String>> {
+    let (tx, rx) = oneshot::channel();
+    matchmaking_tx(ctx)
+        .await?
+        .send(MatchmakingMessage::Leave {
+            discord_id,
+            reply: tx,
+        })
+        .await?;
+
+    Ok(rx.await?)
+}
+
+///Leaderboards with players waiting and how many are in each queue.
+pub async fn list(ctx: &serenity::all::Context) -> Result<Vec<(String, usize)>> {
+    let (tx, rx) = oneshot::channel();
+    matchmaking_tx(ctx)
+        .await?
+        .send(MatchmakingMessage::List { reply: tx })
+        .await?;
+
+    Ok(rx.await?)
+}
+
+async fn matchmaking_tx(ctx: &serenity::all::Context) -> Result<mpsc::Sender<MatchmakingMessage>> {
+    let data = ctx.data.read().await;
+    data.get::<MatchmakingKey>()
+        .cloned()
+        .context("Matchmaking not running.")
+}
+
+///Opens a room for two queued players and posts the link where they joined the queue.
+async fn open_match(ctx: &serenity::all::Context, first: QueuedPlayer, second: QueuedPlayer) {
+    let message = match open_room(ctx, &first, &second).await {
+        Ok(link) => format!(
+            "<@{}> vs <@{}>: your {} match is ready! {}",
+            first.discord_id, second.discord_id, first.lb, link
+        ),
+        Err(e) => format!(
+            "<@{}> <@{}> Failed to make a room for your match: {}. Join the queue again to retry.",
+            first.discord_id, second.discord_id, e
+        ),
+    };
+
+    let _ = first.channel.say(&ctx.http, &message).await;
+    if second.channel != first.channel {
+        let _ = second.channel.say(&ctx.http, &message).await;
+    }
+}
+
+async fn open_room(
+    ctx: &serenity::all::Context,
+    first: &QueuedPlayer,
+    second: &QueuedPlayer,
+) -> Result<String> {
+    let db = {
+        let data = ctx.data.read().await;
+        data.get::<crate::DatabaseKey>().cloned()
+    }
+    .context("Failed to connect to database.")?;
+
+    let room = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT room FROM leaderboard WHERE abbreviation = $1",
+    )
+    .bind(&first.lb)
+    .fetch_optional(db.db.as_ref())
+    .await?
+    .flatten()
+    .context("The leaderboard doesn't have a room config.")?;
+
+    //Players can join under any of their names.
+    let mut whitelist = players::linked_names(db.db.as_ref(), first.discord_id).await?;
+    whitelist.extend(players::linked_names(db.db.as_ref(), second.discord_id).await?);
+
+    let mut room_parameters: RoomParameters = toml::de::from_str(&room)?;
+    room_parameters.name = format!("{} vs {}", first.name, second.name);
+    room_parameters.queue = Queue::Singles;
+    room_parameters.leaderboard = Some(first.lb.clone());
+    room_parameters.whitelist = whitelist;
+    room_parameters.one_game = true;
+
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not running.")?;
+
+    bonk_bot.open_room(ctx, room_parameters).await
+}
@@ -0,0 +1,337 @@
+use anyhow::{anyhow, Context, Result};
+use rand::seq::IndexedRandom;
+use sqlx::{types::time::OffsetDateTime, PgExecutor, Pool, Postgres, Transaction};
+use time::Duration;
+
+const LINK_CODE_CHARACTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const LINK_CODE_LENGTH: usize = 6;
+pub const LINK_CODE_MINUTES: i64 = 10;
+
+///Finds the player that a bonk name belongs to. Returns the player's id and display name.
+pub async fn find<'c>(db: impl PgExecutor<'c>, name: &str) -> Result<Option<(i64, String)>> {
+    Ok(sqlx::query_as(
+        "SELECT p.id, p.name FROM players p \
+        JOIN player_names n ON n.player_id = p.id \
+        WHERE n.name = $1",
+    )
+    .bind(name)
+    .fetch_optional(db)
+    .await?)
+}
+
+///Every bonk name of the player linked to a Discord account.
+pub async fn linked_names<'c>(db: impl PgExecutor<'c>, discord_id: u64) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT n.name FROM players p \
+        JOIN player_names n ON n.player_id = p.id \
+        WHERE p.discord_id = $1",
+    )
+    .bind(discord_id as i64)
+    .fetch_all(db)
+    .await?)
+}
+
+///Finds the player that a bonk name belongs to and creates a new player if there isn't one.
+pub async fn get_or_create(
+    trans: &mut Transaction<'static, Postgres>,
+    name: &str,
+) -> Result<(i64, String)> {
+    if let Some(player) = find(&mut **trans, name).await? {
+        return Ok(player);
+    }
+
+    let id: i64 = sqlx::query_scalar("INSERT INTO players (name) VALUES ($1) RETURNING id")
+        .bind(name)
+        .fetch_one(&mut **trans)
+        .await?;
+    sqlx::query("INSERT INTO player_names (name, player_id) VALUES ($1, $2)")
+        .bind(name)
+        .bind(id)
+        .execute(&mut **trans)
+        .await?;
+
+    Ok((id, name.to_string()))
+}
+
+pub async fn info(db: &Pool<Postgres>, name: &str) -> Result<String> {
+    let (id, display_name) = find(db, name).await?.context("Player not found.")?;
+
+    let names: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM player_names WHERE player_id = $1 ORDER BY name")
+            .bind(id)
+            .fetch_all(db)
+            .await?;
+    let leaderboards: Vec<(String, f64)> = sqlx::query_as(
+        "SELECT lb.abbreviation, l.display_rating FROM lb_players l \
+        JOIN leaderboard lb ON lb.id = l.lb_id \
+        WHERE l.player_id = $1 ORDER BY lb.abbreviation",
+    )
+    .bind(id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(format!(
+        "{} (player {})\nBonk names: {}\nLeaderboards: {}",
+        display_name,
+        id,
+        names.join(", "),
+        if leaderboards.is_empty() {
+            "none".to_string()
+        } else {
+            leaderboards
+                .iter()
+                .map(|(abbreviation, rating)| format!("{} ({:.0})", abbreviation, rating))
+                .collect::<Vec<String>>()
+                .join(", ")
+        },
+    ))
+}
+
+///Adds another bonk name to an existing player.
+pub async fn add_alias(db: &Pool<Postgres>, name: &str, alias: &str) -> Result<String> {
+    let mut trans = db.begin().await?;
+    let (id, display_name) = find(&mut *trans, name)
+        .await?
+        .context("Player not found.")?;
+
+    if let Some((_, other_name)) = find(&mut *trans, alias).await? {
+        return Err(anyhow!(
+            "{} already belongs to {}. Use \"player merge\" to combine the two players.",
+            alias,
+            other_name
+        ));
+    }
+
+    sqlx::query("INSERT INTO player_names (name, player_id) VALUES ($1, $2)")
+        .bind(alias)
+        .bind(id)
+        .execute(&mut *trans)
+        .await?;
+    trans.commit().await?;
+
+    Ok(format!("{} is now a name of {}.", alias, display_name))
+}
+
+pub async fn remove_alias(db: &Pool<Postgres>, alias: &str) -> Result<String> {
+    let mut trans = db.begin().await?;
+    let (id, display_name) = find(&mut *trans, alias)
+        .await?
+        .context("Player not found.")?;
+
+    let names: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM player_names WHERE player_id = $1")
+        .bind(id)
+        .fetch_one(&mut *trans)
+        .await?;
+    if names <= 1 {
+        return Err(anyhow!("{} is the only name of {}.", alias, display_name));
+    }
+
+    sqlx::query("DELETE FROM player_names WHERE name = $1")
+        .bind(alias)
+        .execute(&mut *trans)
+        .await?;
+    trans.commit().await?;
+
+    Ok(format!(
+        "{} is no longer a name of {}.",
+        alias, display_name
+    ))
+}
+
+///Changes the name that a player is shown as on leaderboards.
+pub async fn rename(db: &Pool<Postgres>, name: &str, new_name: &str) -> Result<String> {
+    let mut trans = db.begin().await?;
+    let (id, display_name) = find(&mut *trans, name)
+        .await?
+        .context("Player not found.")?;
+
+    sqlx::query("UPDATE players SET name = $1 WHERE id = $2")
+        .bind(new_name)
+        .bind(id)
+        .execute(&mut *trans)
+        .await?;
+    sqlx::query("UPDATE lb_players SET name = $1 WHERE player_id = $2")
+        .bind(new_name)
+        .bind(id)
+        .execute(&mut *trans)
+        .await?;
+    trans.commit().await?;
+
+    Ok(format!("Renamed {} to {}.", display_name, new_name))
+}
+
+///Moves every name and game of the second player to the first player, and its Discord link if
+///the first player has none. Fails if the players played in the same game. Returns the
+///abbreviations of leaderboards that both players were on since they need to be recalculated.
+pub async fn merge(db: &Pool<Postgres>, name: &str, other: &str) -> Result<(String, Vec<String>)> {
+    let mut trans = db.begin().await?;
+    let (id, display_name) = find(&mut *trans, name)
+        .await?
+        .context(format!("Player \"{}\" not found.", name))?;
+    let (other_id, other_display_name) = find(&mut *trans, other)
+        .await?
+        .context(format!("Player \"{}\" not found.", other))?;
+    if id == other_id {
+        return Err(anyhow!("{} and {} are the same player.", name, other));
+    }
+
+    sqlx::query("UPDATE player_names SET player_id = $1 WHERE player_id = $2")
+        .bind(id)
+        .bind(other_id)
+        .execute(&mut *trans)
+        .await?;
+
+    //(leaderboard abbreviation, lb_players id to keep, lb_players id to remove)
+    let shared: Vec<(String, i64, i64)> = sqlx::query_as(
+        "SELECT lb.abbreviation, a.id, b.id FROM lb_players a \
+        JOIN lb_players b ON b.lb_id = a.lb_id \
+        JOIN leaderboard lb ON lb.id = a.lb_id \
+        WHERE a.player_id = $1 AND b.player_id = $2",
+    )
+    .bind(id)
+    .bind(other_id)
+    .fetch_all(&mut *trans)
+    .await?;
+
+    //Games between the two players can't be merged since the player would be rated against
+    //themself.
+    let mut games: Vec<i64> = vec![];
+    for (_, lb_player_id, other_lb_player_id) in &shared {
+        games.extend(
+            sqlx::query_scalar::<_, i64>(
+                "SELECT DISTINCT a.game_id FROM lb_game_teams a \
+                JOIN lb_game_teams b ON b.game_id = a.game_id \
+                WHERE $1 = ANY(a.player_ids) AND $2 = ANY(b.player_ids)",
+            )
+            .bind(lb_player_id)
+            .bind(other_lb_player_id)
+            .fetch_all(&mut *trans)
+            .await?,
+        );
+    }
+    if !games.is_empty() {
+        return Err(anyhow!(
+            "{} and {} played in the same games. Void them with \"match void\" first: {}",
+            display_name,
+            other_display_name,
+            games
+                .iter()
+                .map(|game| game.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+    }
+
+    for (_, lb_player_id, other_lb_player_id) in &shared {
+        sqlx::query(
+            "UPDATE lb_game_teams SET player_ids = array_replace(player_ids, $1, $2) \
+            WHERE $1 = ANY(player_ids)",
+        )
+        .bind(other_lb_player_id)
+        .bind(lb_player_id)
+        .execute(&mut *trans)
+        .await?;
+        sqlx::query("UPDATE lb_season_standings SET lb_player_id = $1 WHERE lb_player_id = $2")
+            .bind(lb_player_id)
+            .bind(other_lb_player_id)
+            .execute(&mut *trans)
+            .await?;
+        sqlx::query("DELETE FROM lb_players WHERE id = $1")
+            .bind(other_lb_player_id)
+            .execute(&mut *trans)
+            .await?;
+    }
+
+    sqlx::query("UPDATE lb_players SET player_id = $1, name = $2 WHERE player_id = $3")
+        .bind(id)
+        .bind(&display_name)
+        .bind(other_id)
+        .execute(&mut *trans)
+        .await?;
+    let discord_id: Option<i64> =
+        sqlx::query_scalar("DELETE FROM players WHERE id = $1 RETURNING discord_id")
+            .bind(other_id)
+            .fetch_one(&mut *trans)
+            .await?;
+    if let Some(discord_id) = discord_id {
+        sqlx::query("UPDATE players SET discord_id = $1 WHERE id = $2 AND discord_id IS NULL")
+            .bind(discord_id)
+            .bind(id)
+            .execute(&mut *trans)
+            .await?;
+    }
+
+    trans.commit().await?;
+
+    Ok((
+        format!("Merged {} into {}.", other_display_name, display_name),
+        shared
+            .into_iter()
+            .map(|(abbreviation, _, _)| abbreviation)
+            .collect(),
+    ))
+}
+
+///Creates a one-time code that links a Discord user to the bonk account that types it in a room.
+pub async fn create_link_code(db: &Pool<Postgres>, discord_id: u64) -> Result<String> {
+    let code: String = {
+        let mut rng = rand::rng();
+        (0..LINK_CODE_LENGTH)
+            .filter_map(|_| LINK_CODE_CHARACTERS.choose(&mut rng))
+            .map(|c| *c as char)
+            .collect()
+    };
+
+    sqlx::query(
+        "INSERT INTO link_codes (code, discord_id, expires) VALUES ($1, $2, $3) \
+        ON CONFLICT (discord_id) DO UPDATE SET code = $1, expires = $3",
+    )
+    .bind(&code)
+    .bind(discord_id as i64)
+    .bind(OffsetDateTime::now_utc() + Duration::minutes(LINK_CODE_MINUTES))
+    .execute(db)
+    .await?;
+
+    Ok(code)
+}
+
+///Uses up a link code and links its Discord user to the player with the bonk name.
+///Returns the Discord user's id.
+pub async fn link(db: &Pool<Postgres>, code: &str, name: &str) -> Result<u64> {
+    let mut trans = db.begin().await?;
+
+    let discord_id: i64 = sqlx::query_scalar(
+        "DELETE FROM link_codes WHERE code = $1 AND expires > $2 RETURNING discord_id",
+    )
+    .bind(code.to_uppercase())
+    .bind(OffsetDateTime::now_utc())
+    .fetch_optional(&mut *trans)
+    .await?
+    .context("Invalid or expired link code.")?;
+
+    let (player_id, _) = get_or_create(&mut trans, name).await?;
+
+    sqlx::query("UPDATE players SET discord_id = NULL WHERE discord_id = $1")
+        .bind(discord_id)
+        .execute(&mut *trans)
+        .await?;
+    sqlx::query("UPDATE players SET discord_id = $1 WHERE id = $2")
+        .bind(discord_id)
+        .bind(player_id)
+        .execute(&mut *trans)
+        .await?;
+
+    trans.commit().await?;
+
+    Ok(discord_id as u64)
+}
+
+///Removes the link of a Discord user. Returns the display name of the player that was linked.
+pub async fn unlink(db: &Pool<Postgres>, discord_id: u64) -> Result<Option<String>> {
+    Ok(sqlx::query_scalar(
+        "UPDATE players SET discord_id = NULL WHERE discord_id = $1 RETURNING name",
+    )
+    .bind(discord_id as i64)
+    .fetch_optional(db)
+    .await?)
+}
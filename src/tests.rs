@@ -1,10 +1,44 @@
 use time::{Date, Month, OffsetDateTime};
 
 use crate::{
-    bonk_bot::bonk_commands::fuzzy_finder,
-    leaderboard::{self, openskill, LeaderboardSettings, PlayerData},
+    bonk_bot::{
+        bonk_commands::fuzzy_finder,
+        bonk_room::draft_picker,
+        events::{teams_start, TeamsStart},
+        room_maker::{DraftOrder, RoomParameters, TeamFormation},
+    },
+    challenges::parse_time,
+    leaderboard::{
+        self, glicko2, openskill, stats, whr, GameRecord, LeaderboardSettings, PlayerData,
+        RatingAlgorithm,
+    },
+    matchmaking::{find_pair, QueuedPlayer},
+    tournament::bracket::{Bracket, Format},
 };
 
+///Glicko-2 defaults with no optional settings. Tests override what they need.
+fn test_settings(algorithm: RatingAlgorithm) -> LeaderboardSettings {
+    LeaderboardSettings {
+        name: "".to_string(),
+        abbreviation: "".to_string(),
+        algorithm,
+        mean_rating: 1500.,
+        rating_scale: 173.7178,
+        unrated_deviation: 2.,
+        deviation_per_day: 0.,
+        cre: None,
+        volatility: None,
+        tau: None,
+        soft_reset: None,
+        season_placements: None,
+        provisional_games: None,
+        provisional_deviation: None,
+        inactive_days: None,
+        score_margin: None,
+        map_ratings: None,
+    }
+}
+
 #[test]
 fn fuzzy_finder_test() {
     let output = fuzzy_finder(
@@ -28,7 +62,10 @@ fn match_string() {
         display_rating: 1600.,
         old_rating: 1500.,
         rating_deviation: 0.,
+        volatility: 0.06,
         last_updated: Date::from_calendar_date(2025, Month::January, 1).unwrap(),
+        last_played: None,
+        provisional: false,
     };
     let mut player2 = player.clone();
     player2.name = "F A C T S 2".to_string();
@@ -50,14 +87,11 @@ fn openskill() {
     let today = OffsetDateTime::now_utc().date();
 
     let settings = LeaderboardSettings {
-        name: "".to_string(),
-        abbreviation: "".to_string(),
-        algorithm: leaderboard::RatingAlgorithm::OpenSkill,
         mean_rating: 5000.,
         rating_scale: 1000.,
-        unrated_deviation: 2.,
         deviation_per_day: 0.0523,
         cre: Some(1.),
+        ..test_settings(RatingAlgorithm::OpenSkill)
     };
 
     let player = PlayerData {
@@ -67,7 +101,10 @@ fn openskill() {
         display_rating: 1500.,
         old_rating: 5000.,
         rating_deviation: 2000.,
+        volatility: 0.06,
         last_updated: today,
+        last_played: None,
+        provisional: false,
     };
 
     let mut player2 = player.clone();
@@ -76,11 +113,400 @@ fn openskill() {
 
     let mut teams_data = vec![vec![player.clone()], vec![player2.clone()]];
 
-    openskill::reverse_pl(&settings, &vec![true], &mut teams_data);
+    openskill::reverse_pl(&settings, &vec![true], &mut teams_data, today);
 
     dbg!(teams_data);
 }
 
+#[test]
+fn glicko2() {
+    let today = OffsetDateTime::now_utc().date();
+
+    //Example from Glickman's "Example of the Glicko-2 system".
+    let settings = LeaderboardSettings {
+        unrated_deviation: 350. / 173.7178,
+        tau: Some(0.5),
+        ..test_settings(RatingAlgorithm::Glicko2)
+    };
+
+    let player = PlayerData {
+        id: 0,
+        name: "StarCubey".to_string(),
+        rating: 1500.,
+        display_rating: 1500.,
+        old_rating: 1500.,
+        rating_deviation: 200.,
+        volatility: 0.06,
+        last_updated: today,
+        last_played: None,
+        provisional: false,
+    };
+    let opponent = |rating: f64, rating_deviation: f64| PlayerData {
+        rating,
+        rating_deviation,
+        ..player.clone()
+    };
+
+    let mut teams_data = vec![
+        vec![opponent(1700., 300.)],
+        vec![opponent(1550., 100.)],
+        vec![player.clone()],
+        vec![opponent(1400., 30.)],
+    ];
+
+    glicko2::glicko2(&settings, &[false, false, false], &mut teams_data, today);
+
+    let player = &teams_data[2][0];
+    assert!((player.rating - 1464.06).abs() < 0.1);
+    assert!((player.rating_deviation - 151.52).abs() < 0.1);
+    assert!((player.volatility - 0.05999).abs() < 0.0001);
+}
+
+#[test]
+fn whr() {
+    let settings = LeaderboardSettings {
+        rating_scale: 173.717793,
+        unrated_deviation: 2.014761,
+        deviation_per_day: 0.037,
+        ..test_settings(RatingAlgorithm::WHR)
+    };
+
+    let game = |day: u8, teams: Vec<Vec<i64>>, ties: Vec<bool>| GameRecord {
+        id: 0,
+        season_num: 0,
+        day: Date::from_calendar_date(2025, Month::January, day).unwrap(),
+        score: None,
+        ties,
+        map_id: None,
+        teams,
+        old_rating: vec![],
+        new_rating: vec![],
+        new_deviation: vec![],
+        sides: vec![],
+    };
+    let games = vec![
+        game(1, vec![vec![1], vec![2]], vec![false]),
+        game(1, vec![vec![1], vec![2]], vec![false]),
+        game(3, vec![vec![2], vec![1]], vec![true]),
+        game(4, vec![vec![1], vec![2]], vec![false]),
+    ];
+
+    let ratings = whr::whr(&settings, &games);
+
+    let player1 = ratings.get(&1).unwrap();
+    let player2 = ratings.get(&2).unwrap();
+    assert_eq!(player1.len(), 3);
+    let (player1, player2) = (player1.last().unwrap(), player2.last().unwrap());
+    assert!(player1.rating > 1500.);
+    assert!((player1.rating - 1500. - (1500. - player2.rating)).abs() < 0.01);
+    assert!(player1.rating_deviation < settings.unrated_deviation * settings.rating_scale);
+}
+
+#[test]
+fn single_elimination() {
+    let players = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+    let mut bracket = Bracket::new(Format::SingleElimination, players, None);
+
+    //A has a bye into the final against the winner of B and C.
+    assert!(bracket.matches[0].done);
+    assert_eq!(bracket.unopened_matches(), vec![1]);
+    assert_eq!(bracket.matches[1].players, [Some(1), Some(2)]);
+    bracket.report(1, 1).unwrap();
+    assert_eq!(bracket.unopened_matches(), vec![2]);
+    assert_eq!(bracket.matches[2].players, [Some(0), Some(1)]);
+    assert!(bracket.report(2, 2).is_err());
+    bracket.report(2, 1).unwrap();
+
+    assert!(bracket.finished());
+    assert_eq!(bracket.standings(), vec![1]);
+}
+
+#[test]
+fn swiss() {
+    let players = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+    let mut bracket = Bracket::new(Format::Swiss, players, None);
+    assert_eq!(bracket.rounds, 2);
+
+    //The lowest seed gets the first bye.
+    assert_eq!(bracket.matches[0].players, [Some(2), None]);
+    assert_eq!(bracket.unopened_matches(), vec![1]);
+    assert_eq!(bracket.matches[1].players, [Some(0), Some(1)]);
+    bracket.report(1, 0).unwrap();
+
+    //B is the only player left without a bye and A and C haven't played yet.
+    assert_eq!(bracket.matches[2].players, [Some(1), None]);
+    assert_eq!(bracket.unopened_matches(), vec![3]);
+    assert_eq!(bracket.matches[3].players, [Some(0), Some(2)]);
+    bracket.report(3, 0).unwrap();
+
+    assert!(bracket.finished());
+    assert_eq!(bracket.wins(), vec![2, 1, 1]);
+    assert_eq!(bracket.standings()[0], 0);
+}
+
+#[test]
+fn double_elimination() {
+    let players = vec!["A".to_string(), "B".to_string(), "C".to_string()];
+    let mut bracket = Bracket::new(Format::DoubleElimination, players, None);
+
+    //A has a bye and B gets a walkover in the losers bracket after losing to C.
+    assert_eq!(bracket.unopened_matches(), vec![1]);
+    bracket.report(1, 2).unwrap();
+    assert_eq!(bracket.unopened_matches(), vec![2]);
+    bracket.report(2, 0).unwrap();
+    assert_eq!(bracket.unopened_matches(), vec![4]);
+    assert_eq!(bracket.matches[4].players, [Some(1), Some(2)]);
+    bracket.report(4, 2).unwrap();
+    assert!(bracket.report(4, 1).is_err());
+    bracket.report(5, 2).unwrap();
+
+    assert!(bracket.finished());
+    assert_eq!(bracket.standings(), vec![2]);
+}
+
+#[test]
+fn balanced_teams() {
+    let today = OffsetDateTime::now_utc().date();
+
+    let settings = LeaderboardSettings {
+        mean_rating: 25.,
+        rating_scale: 25. / 6.,
+        cre: Some(3.),
+        ..test_settings(RatingAlgorithm::OpenSkill)
+    };
+    let players = [40., 30., 25., 20., 15., 10.]
+        .iter()
+        .map(|rating| PlayerData {
+            id: 0,
+            name: rating.to_string(),
+            rating: *rating,
+            display_rating: *rating,
+            old_rating: *rating,
+            rating_deviation: 2.,
+            volatility: 0.06,
+            last_updated: today,
+            last_played: None,
+            provisional: false,
+        })
+        .collect::<Vec<PlayerData>>();
+
+    //40 + 20 + 10 and 30 + 25 + 15 is the only split with equal ratings.
+    let mut teams = openskill::balanced_teams(&settings, players, 2, today);
+    teams.iter_mut().for_each(|team| team.sort());
+    teams.sort();
+    assert_eq!(teams, vec![vec![0, 3, 5], vec![1, 2, 4]]);
+}
+
+#[test]
+fn predictions() {
+    let today = OffsetDateTime::now_utc().date();
+
+    let player = |rating: f64| PlayerData {
+        id: 0,
+        name: rating.to_string(),
+        rating,
+        display_rating: rating,
+        old_rating: rating,
+        rating_deviation: 100.,
+        volatility: 0.06,
+        last_updated: today,
+        last_played: None,
+        provisional: false,
+    };
+    let teams_data = vec![vec![player(1600.)], vec![player(1500.)]];
+
+    //Glicko-2 stakes come from a Glicko-2 update and grow with the score margin.
+    let settings = test_settings(RatingAlgorithm::Glicko2);
+    let prediction = openskill::team_predictions(&settings, &teams_data, today);
+    let expected = stats::win_probability(&settings, (1600., 100.), (1500., 100.));
+    assert!((prediction[0].win_probability - expected).abs() < 1e-9);
+    let mut won = teams_data.clone();
+    glicko2::glicko2(&settings, &[false], &mut won, today);
+    let win_change = won[0][0].display_rating - 1600.;
+    assert!((prediction[0].win_change.unwrap() - win_change).abs() < 1e-9);
+
+    let settings = LeaderboardSettings {
+        score_margin: Some(0.5),
+        ..settings
+    };
+    let prediction = openskill::team_predictions(&settings, &teams_data, today);
+    assert!((prediction[0].win_change.unwrap() - win_change * 1.5).abs() < 1e-9);
+
+    //WHR only has chances to win.
+    let settings = test_settings(RatingAlgorithm::WHR);
+    let prediction = openskill::team_predictions(&settings, &teams_data, today);
+    assert!(prediction[0].win_probability > 0.5);
+    assert_eq!(prediction[0].win_change, None);
+}
+
+#[test]
+fn score_margin() {
+    let today = OffsetDateTime::now_utc().date();
+
+    let settings = LeaderboardSettings {
+        cre: Some(0.),
+        score_margin: Some(0.5),
+        ..test_settings(RatingAlgorithm::Glicko2)
+    };
+    let player = |rating: f64| PlayerData {
+        id: 0,
+        name: "".to_string(),
+        rating,
+        display_rating: rating,
+        old_rating: rating,
+        rating_deviation: 0.,
+        volatility: 0.06,
+        last_updated: today,
+        last_played: None,
+        provisional: false,
+    };
+    let before = vec![vec![player(1500.)], vec![player(1500.)]];
+    let change = |score: Vec<f64>| {
+        let mut teams_data = vec![vec![player(1520.)], vec![player(1480.)]];
+        leaderboard::apply_score_margin(&settings, Some(&score), &before, &mut teams_data);
+        (
+            teams_data[0][0].rating - 1500.,
+            teams_data[1][0].rating - 1500.,
+        )
+    };
+
+    //A shutout scales changes by 1 + score_margin and a 5-3 game by 1 - score_margin / 2.
+    assert_eq!(change(vec![5., 0.]), (30., -30.));
+    assert_eq!(change(vec![5., 3.]), (15., -15.));
+    //Even and invalid scores leave the changes alone.
+    assert_eq!(change(vec![3., 3.]), (20., -20.));
+    assert_eq!(change(vec![5., -3.]), (20., -20.));
+}
+
+#[test]
+fn provisional_standings() {
+    let today = OffsetDateTime::now_utc().date();
+
+    let settings = LeaderboardSettings {
+        cre: Some(2.),
+        provisional_games: Some(5),
+        provisional_deviation: Some(100.),
+        inactive_days: Some(30),
+        ..test_settings(RatingAlgorithm::Glicko2)
+    };
+
+    assert!(!settings.is_provisional(5, 100.));
+    assert!(settings.is_provisional(4, 50.));
+    assert!(settings.is_provisional(10, 150.));
+
+    let player = |id: i64, rating_deviation: f64, last_played: i64| PlayerData {
+        id,
+        name: id.to_string(),
+        rating: 1500.,
+        display_rating: 1500.,
+        old_rating: 1500.,
+        rating_deviation,
+        volatility: 0.06,
+        last_updated: today,
+        last_played: Some(today - time::Duration::days(last_played)),
+        provisional: false,
+    };
+    let players = vec![
+        player(0, 80., 1),
+        player(1, 80., 1),
+        player(2, 150., 1),
+        player(3, 80., 40),
+    ];
+    //Player 1 has too few games, player 2 too high a deviation and player 3 is inactive.
+    let games = [(0, 10), (1, 3), (2, 10), (3, 10)].into_iter().collect();
+
+    let standings = leaderboard::Standings::new(&settings, players, &games, today);
+    let ids = |players: &[PlayerData]| players.iter().map(|p| p.id).collect::<Vec<i64>>();
+    assert_eq!(ids(&standings.ranked), vec![0]);
+    assert_eq!(ids(&standings.provisional), vec![1, 2]);
+    assert_eq!(ids(&standings.inactive), vec![3]);
+    assert!(standings.provisional.iter().all(|p| p.provisional));
+    assert!(!standings.ranked[0].provisional);
+}
+
+#[test]
+fn balanced_teams_start() {
+    let mut room_parameters: RoomParameters = toml::de::from_str(concat!(
+        "name = \"Teams\"\n",
+        "max_players = 8\n",
+        "min_level = 0\n",
+        "mode = \"Football\"\n",
+        "queue = \"Teams\"\n",
+        "rounds = 3\n",
+        "team_size = 2\n",
+        "team_num = 2\n",
+        "team_formation = \"balanced\"\n",
+    ))
+    .unwrap();
+
+    //Balanced teams skip the draft and the idle wait once the teams can be filled.
+    assert_eq!(teams_start(&room_parameters, 3), TeamsStart::Wait);
+    assert_eq!(teams_start(&room_parameters, 4), TeamsStart::Balance);
+    room_parameters.team_formation = TeamFormation::Draft;
+    assert_eq!(teams_start(&room_parameters, 4), TeamsStart::Draft);
+}
+
+#[test]
+fn draft_order() {
+    //Captains are already on their teams, so each team has two picks left.
+    let order = |draft_order: DraftOrder| {
+        let mut team_sizes = vec![1, 1];
+        let mut picks = vec![];
+        while let Some(team) = draft_picker(&team_sizes, 3, &draft_order) {
+            team_sizes[team] += 1;
+            picks.push(team);
+        }
+        picks
+    };
+
+    assert_eq!(order(DraftOrder::Straight), vec![0, 1, 0, 1]);
+    assert_eq!(order(DraftOrder::Snake), vec![0, 1, 1, 0]);
+    //The order continues from the picks already made.
+    assert_eq!(draft_picker(&[2, 2], 3, &DraftOrder::Snake), Some(1));
+    assert_eq!(draft_picker(&[3, 3], 3, &DraftOrder::Snake), None);
+}
+
+#[test]
+fn matchmaking_range() {
+    let now = tokio::time::Instant::now();
+    let player = |name: &str, rating: f64, waited: u64| QueuedPlayer {
+        discord_id: rating as u64,
+        name: name.to_string(),
+        lb: "c1".to_string(),
+        rating,
+        rating_scale: 100.,
+        channel: serenity::all::ChannelId::new(1),
+        joined: now - std::time::Duration::from_secs(waited),
+    };
+
+    //Both ranges start at 50, so nobody is paired yet.
+    let mut queue = vec![
+        player("A", 1500., 0),
+        player("B", 1600., 0),
+        player("C", 1420., 0),
+    ];
+    assert_eq!(find_pair(&queue, now), None);
+
+    //After a minute A accepts a 100 point difference and is paired with the closer C.
+    queue[0].joined = now - std::time::Duration::from_secs(60);
+    assert_eq!(find_pair(&queue, now), Some((0, 2)));
+}
+
+#[test]
+fn challenge_time() {
+    assert_eq!(
+        parse_time("<t:1700000000:f>"),
+        OffsetDateTime::from_unix_timestamp(1700000000).ok()
+    );
+    assert!(parse_time("1d12h").is_some());
+
+    //Bare numbers are leaderboard abbreviations, and huge delays are rejected instead of overflowing.
+    assert_eq!(parse_time("1700000000"), None);
+    assert_eq!(parse_time("31d"), None);
+    assert_eq!(parse_time("99999999999999999999d"), None);
+    assert_eq!(parse_time("9999999999999999d"), None);
+}
+
 #[test]
 fn test() {
     let ties = vec![false, true, true, false];
@@ -0,0 +1,621 @@
+pub mod bracket;
+
+use anyhow::{anyhow, Context, Result};
+use serenity::all::ChannelId;
+use serenity::prelude::TypeMapKey;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::sync::mpsc;
+
+use crate::bonk_bot::room_maker::{Queue, RoomParameters};
+use crate::bonk_bot::BonkBotKey;
+use bracket::{Bracket, Format, Stage};
+
+const DISCORD_CHARACTER_LIMIT: usize = 2000;
+
+///A bracket match played in a bonk room.
+#[derive(Clone, Debug)]
+pub struct TournamentMatch {
+    pub tournament_id: i64,
+    pub match_idx: usize,
+    ///Bracket index and every bonk name of both players.
+    pub players: [(usize, Vec<String>); 2],
+}
+
+pub struct TournamentKey;
+
+impl TypeMapKey for TournamentKey {
+    type Value = mpsc::Sender<TournamentMessage>;
+}
+
+///buffer 10, blocking send
+pub enum TournamentMessage {
+    Result {
+        tournament_id: i64,
+        match_idx: usize,
+        winner: usize,
+    },
+}
+
+///Takes results from tournament rooms. Opening the rooms of the next matches needs the
+///Discord context, which rooms don't have.
+pub struct TournamentRunner {
+    rx: mpsc::Receiver<TournamentMessage>,
+    ctx: serenity::all::Context,
+}
+
+impl TournamentRunner {
+    pub fn start(ctx: serenity::all::Context) -> mpsc::Sender<TournamentMessage> {
+        let (tx, rx) = mpsc::channel(10);
+        let mut runner = TournamentRunner { rx, ctx };
+        tokio::spawn(async move { runner.run().await });
+        tx
+    }
+
+    async fn run(&mut self) {
+        while let Some(message) = self.rx.recv().await {
+            match message {
+                TournamentMessage::Result {
+                    tournament_id,
+                    match_idx,
+                    winner,
+                } => {
+                    if let Err(e) = report(&self.ctx, tournament_id, match_idx, winner).await {
+                        println!("Failed to report tournament result: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Tournament {
+    id: i64,
+    name: String,
+    lb: String,
+    channel: u64,
+    room: String,
+}
+
+///Creates a tournament and opens signups.
+pub async fn create(
+    db: &Pool<Postgres>,
+    name: &str,
+    lb: &str,
+    format: Format,
+    rounds: Option<usize>,
+    channel: u64,
+    room: &str,
+) -> Result<String> {
+    toml::de::from_str::<RoomParameters>(room).context("Invalid room config.")?;
+    let lb_id: i64 = sqlx::query_scalar("SELECT id FROM leaderboard WHERE abbreviation = $1")
+        .bind(lb)
+        .fetch_optional(db)
+        .await?
+        .context("Leaderboard not found.")?;
+
+    let created = sqlx::query(
+        "INSERT INTO tournaments (name, lb_id, format, rounds, channel, room) \
+        VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING",
+    )
+    .bind(name)
+    .bind(lb_id)
+    .bind(serde_json::to_value(format)?)
+    .bind(rounds.map(|rounds| rounds as i32))
+    .bind(channel as i64)
+    .bind(room)
+    .execute(db)
+    .await?;
+    if created.rows_affected() == 0 {
+        return Err(anyhow!("A tournament named {} already exists.", name));
+    }
+
+    let message = format!(
+        "Signups for {} ({}) are open! Use \"/elo tournament join {}\" to join.",
+        name,
+        format.name(),
+        name
+    );
+    Ok(message)
+}
+
+pub async fn delete(db: &Pool<Postgres>, name: &str) -> Result<String> {
+    let deleted = sqlx::query("DELETE FROM tournaments WHERE name = $1")
+        .bind(name)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(anyhow!("Unable to find tournament {}.", name));
+    }
+
+    Ok(format!("Deleted tournament {}.", name))
+}
+
+///Signs up the bonk player linked to a Discord account.
+pub async fn join(db: &Pool<Postgres>, name: &str, discord_id: u64) -> Result<String> {
+    let (player_id, player_name) = linked_player(db, discord_id).await?;
+    let (tournament_id, started) = signup_state(db, name).await?;
+    if started {
+        return Err(anyhow!("Signups for {} are closed.", name));
+    }
+
+    let joined = sqlx::query(
+        "INSERT INTO tournament_players (tournament_id, player_id) VALUES ($1, $2) \
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(tournament_id)
+    .bind(player_id)
+    .execute(db)
+    .await?;
+    if joined.rows_affected() == 0 {
+        return Err(anyhow!("You already joined {}.", name));
+    }
+
+    Ok(format!("{} joined {}!", player_name, name))
+}
+
+pub async fn leave(db: &Pool<Postgres>, name: &str, discord_id: u64) -> Result<String> {
+    let (player_id, player_name) = linked_player(db, discord_id).await?;
+    let (tournament_id, started) = signup_state(db, name).await?;
+    if started {
+        return Err(anyhow!("{} has already started.", name));
+    }
+
+    let left =
+        sqlx::query("DELETE FROM tournament_players WHERE tournament_id = $1 AND player_id = $2")
+            .bind(tournament_id)
+            .bind(player_id)
+            .execute(db)
+            .await?;
+    if left.rows_affected() == 0 {
+        return Err(anyhow!("You aren't signed up for {}.", name));
+    }
+
+    Ok(format!("{} left {}.", player_name, name))
+}
+
+///Lists every tournament with its number of players.
+pub async fn list(db: &Pool<Postgres>) -> Result<String> {
+    let tournaments: Vec<(String, serde_json::Value, bool, i64)> = sqlx::query_as(
+        "SELECT t.name, t.format, t.bracket IS NOT NULL, COUNT(p.player_id) FROM tournaments t \
+        LEFT JOIN tournament_players p ON p.tournament_id = t.id GROUP BY t.id ORDER BY t.id",
+    )
+    .fetch_all(db)
+    .await?;
+    if tournaments.is_empty() {
+        return Ok("Tournaments:\n\nNo tournaments.".to_string());
+    }
+
+    let mut lines = vec![];
+    for (name, format, started, players) in tournaments {
+        let format: Format = serde_json::from_value(format)?;
+        lines.push(format!(
+            "{} ({}): {} players, {}",
+            name,
+            format.name(),
+            players,
+            if started { "started" } else { "signups open" }
+        ));
+    }
+
+    Ok(format!("Tournaments:\n\n{}", lines.join("\n")))
+}
+
+///Shows the signups or the bracket of a tournament.
+pub async fn show(db: &Pool<Postgres>, name: &str) -> Result<String> {
+    let (tournament_id, format, bracket): (i64, serde_json::Value, Option<serde_json::Value>) =
+        sqlx::query_as("SELECT id, format, bracket FROM tournaments WHERE name = $1")
+            .bind(name)
+            .fetch_optional(db)
+            .await?
+            .with_context(|| format!("Unable to find tournament {}.", name))?;
+
+    if let Some(bracket) = bracket {
+        let bracket: Bracket = serde_json::from_value(bracket)?;
+        return Ok(bracket_string(name, &bracket));
+    }
+
+    let format: Format = serde_json::from_value(format)?;
+    let players = seeded_players(db, tournament_id).await?;
+    Ok(format!(
+        "**{}** ({})\nSignups are open. {} players:\n{}",
+        name,
+        format.name(),
+        players.len(),
+        players
+            .iter()
+            .map(|(_, name)| name.clone())
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
+///Seeds the players by rating, builds the bracket and opens rooms for the first matches.
+pub async fn start(ctx: &serenity::all::Context, name: &str) -> Result<()> {
+    let db = database(ctx).await?;
+    let mut trans = db.begin().await?;
+
+    let (tournament_id, format, rounds, started): (i64, serde_json::Value, Option<i32>, bool) =
+        sqlx::query_as(
+            "SELECT id, format, rounds, bracket IS NOT NULL FROM tournaments \
+            WHERE name = $1 FOR UPDATE",
+        )
+        .bind(name)
+        .fetch_optional(&mut *trans)
+        .await?
+        .with_context(|| format!("Unable to find tournament {}.", name))?;
+    if started {
+        return Err(anyhow!("{} has already started.", name));
+    }
+
+    let players = seeded_players(&mut *trans, tournament_id).await?;
+    if players.len() < 2 {
+        return Err(anyhow!("A tournament needs at least 2 players."));
+    }
+    for (seed, (player_id, _)) in players.iter().enumerate() {
+        sqlx::query(
+            "UPDATE tournament_players SET seed = $1 WHERE tournament_id = $2 AND player_id = $3",
+        )
+        .bind(seed as i32)
+        .bind(tournament_id)
+        .bind(player_id)
+        .execute(&mut *trans)
+        .await?;
+    }
+
+    let mut bracket = Bracket::new(
+        serde_json::from_value(format)?,
+        players.into_iter().map(|(_, name)| name).collect(),
+        rounds.map(|rounds| rounds as usize),
+    );
+    let new_matches = open_matches(&mut bracket);
+    save_bracket(&mut trans, tournament_id, &bracket).await?;
+    trans.commit().await?;
+
+    advance(ctx, tournament_id, &bracket, new_matches, None).await
+}
+
+///Enters a result by the winner's name. Used when a match wasn't played in a bot room.
+pub async fn report_by_name(
+    ctx: &serenity::all::Context,
+    name: &str,
+    match_number: usize,
+    winner: &str,
+) -> Result<()> {
+    let db = database(ctx).await?;
+    let (tournament_id, bracket): (i64, Option<serde_json::Value>) =
+        sqlx::query_as("SELECT id, bracket FROM tournaments WHERE name = $1")
+            .bind(name)
+            .fetch_optional(db.as_ref())
+            .await?
+            .with_context(|| format!("Unable to find tournament {}.", name))?;
+    let bracket: Bracket =
+        serde_json::from_value(bracket.with_context(|| format!("{} hasn't started.", name))?)?;
+
+    let winner = bracket
+        .players
+        .iter()
+        .position(|player| player.eq_ignore_ascii_case(winner))
+        .with_context(|| format!("{} isn't in {}.", winner, name))?;
+
+    report(
+        ctx,
+        tournament_id,
+        match_number
+            .checked_sub(1)
+            .context("Invalid match number.")?,
+        winner,
+    )
+    .await
+}
+
+///Records the winner of a match and opens rooms for the matches that are now ready.
+pub async fn report(
+    ctx: &serenity::all::Context,
+    tournament_id: i64,
+    match_idx: usize,
+    winner: usize,
+) -> Result<()> {
+    let db = database(ctx).await?;
+    let mut trans = db.begin().await?;
+
+    let bracket: Option<serde_json::Value> =
+        sqlx::query_scalar("SELECT bracket FROM tournaments WHERE id = $1 FOR UPDATE")
+            .bind(tournament_id)
+            .fetch_optional(&mut *trans)
+            .await?
+            .flatten();
+    let mut bracket: Bracket =
+        serde_json::from_value(bracket.context("The tournament hasn't started.")?)?;
+    bracket.report(match_idx, winner)?;
+    let new_matches = open_matches(&mut bracket);
+    save_bracket(&mut trans, tournament_id, &bracket).await?;
+    trans.commit().await?;
+
+    let result = bracket.matches.get(match_idx).map(|bracket_match| {
+        format!(
+            "{} beat {} in match #{}.",
+            bracket.player_name(bracket_match.winner),
+            bracket.player_name(bracket_match.loser()),
+            match_idx + 1
+        )
+    });
+    advance(ctx, tournament_id, &bracket, new_matches, result).await
+}
+
+///Opens rooms for new matches and posts the bracket to the tournament channel.
+async fn advance(
+    ctx: &serenity::all::Context,
+    tournament_id: i64,
+    bracket: &Bracket,
+    new_matches: Vec<usize>,
+    result: Option<String>,
+) -> Result<()> {
+    let db = database(ctx).await?;
+    let (id, name, lb, channel, room): (i64, String, String, i64, String) = sqlx::query_as(
+        "SELECT t.id, t.name, l.abbreviation, t.channel, t.room FROM tournaments t \
+        JOIN leaderboard l ON l.id = t.lb_id WHERE t.id = $1",
+    )
+    .bind(tournament_id)
+    .fetch_one(db.as_ref())
+    .await?;
+    let tournament = Tournament {
+        id,
+        name,
+        lb,
+        channel: channel as u64,
+        room,
+    };
+    let channel = ChannelId::new(tournament.channel);
+
+    //Matches are already marked as opened, so a failed post can't stop their rooms from opening.
+    if let Some(result) = result {
+        post(ctx, channel, result).await;
+    }
+    post(ctx, channel, bracket_string(&tournament.name, bracket)).await;
+
+    if bracket.finished() {
+        let standings = bracket.standings();
+        let message = if let Format::Swiss = bracket.format {
+            let wins = bracket.wins();
+            format!(
+                "{} is over! Final standings:\n{}",
+                tournament.name,
+                standings
+                    .iter()
+                    .enumerate()
+                    .map(|(i, player)| format!(
+                        "{}. {} ({} wins)",
+                        i + 1,
+                        bracket.player_name(Some(*player)),
+                        wins.get(*player).copied().unwrap_or(0)
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            )
+        } else {
+            format!(
+                "{} won {}!",
+                bracket.player_name(standings.first().copied()),
+                tournament.name
+            )
+        };
+        post(ctx, channel, message).await;
+        return Ok(());
+    }
+
+    for match_idx in new_matches {
+        let line = match open_room(ctx, &db, &tournament, bracket, match_idx).await {
+            Ok(link) => link,
+            Err(e) => format!(
+                "Failed to make room: {}. Use \"/elo a tournament report\" to enter the result.",
+                e
+            ),
+        };
+        let players = bracket
+            .matches
+            .get(match_idx)
+            .map(|bracket_match| {
+                bracket_match
+                    .players
+                    .map(|player| bracket.player_name(player))
+                    .join(" vs ")
+            })
+            .unwrap_or_default();
+        post(
+            ctx,
+            channel,
+            format!("Match #{}: {}\n{}", match_idx + 1, players, line),
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+async fn post(ctx: &serenity::all::Context, channel: ChannelId, message: String) {
+    if let Err(e) = channel.say(&ctx.http, message).await {
+        println!("Failed to post to tournament channel: {e}");
+    }
+}
+
+///Opens a room that only the two players of a match can join.
+async fn open_room(
+    ctx: &serenity::all::Context,
+    db: &Pool<Postgres>,
+    tournament: &Tournament,
+    bracket: &Bracket,
+    match_idx: usize,
+) -> Result<String> {
+    let bracket_match = bracket
+        .matches
+        .get(match_idx)
+        .context("That match doesn't exist.")?;
+    let [Some(first), Some(second)] = bracket_match.players else {
+        return Err(anyhow!("The players of that match aren't known yet."));
+    };
+
+    let first_names = player_names(db, tournament.id, first).await?;
+    let second_names = player_names(db, tournament.id, second).await?;
+
+    let mut room_parameters: RoomParameters = toml::de::from_str(&tournament.room)?;
+    room_parameters.name = format!("{} #{}", room_parameters.name, match_idx + 1);
+    room_parameters.queue = Queue::Singles;
+    room_parameters.leaderboard = Some(tournament.lb.clone());
+    room_parameters.whitelist = first_names
+        .iter()
+        .chain(second_names.iter())
+        .cloned()
+        .collect();
+    room_parameters.tournament_match = Some(TournamentMatch {
+        tournament_id: tournament.id,
+        match_idx,
+        players: [(first, first_names), (second, second_names)],
+    });
+
+    let bonk_bot = {
+        let data = ctx.data.read().await;
+        data.get::<BonkBotKey>().cloned()
+    }
+    .context("Bonk bot not running.")?;
+
+    bonk_bot.open_room(ctx, room_parameters).await
+}
+
+///Marks the matches that are ready as opened and returns them.
+fn open_matches(bracket: &mut Bracket) -> Vec<usize> {
+    let new_matches = bracket.unopened_matches();
+    for match_idx in &new_matches {
+        if let Some(bracket_match) = bracket.matches.get_mut(*match_idx) {
+            bracket_match.opened = true;
+        }
+    }
+    new_matches
+}
+
+///The bracket as a code block. Matches whose players aren't known show TBD.
+fn bracket_string(name: &str, bracket: &Bracket) -> String {
+    let mut lines = vec![];
+    let mut last_round = None;
+    for (i, bracket_match) in bracket.matches.iter().enumerate() {
+        let round = (bracket_match.stage, bracket_match.round);
+        if last_round != Some(round) {
+            last_round = Some(round);
+            lines.push(match bracket_match.stage {
+                Stage::Winners if bracket.format == Format::SingleElimination => {
+                    format!("Round {}", bracket_match.round + 1)
+                }
+                Stage::Winners => format!("Winners round {}", bracket_match.round + 1),
+                Stage::Losers => format!("Losers round {}", bracket_match.round + 1),
+                Stage::GrandFinal => "Grand final".to_string(),
+                Stage::Swiss => format!("Round {}", bracket_match.round + 1),
+            });
+        }
+
+        let players = bracket_match.players.map(|player| match player {
+            Some(_) => bracket.player_name(player),
+            None if bracket_match.done => "bye".to_string(),
+            None => "TBD".to_string(),
+        });
+        let status = if !bracket_match.done {
+            String::new()
+        } else if bracket_match.players.contains(&None) {
+            " (bye)".to_string()
+        } else {
+            format!(" - {} won", bracket.player_name(bracket_match.winner))
+        };
+        lines.push(format!(
+            "  #{} {} vs {}{}",
+            i + 1,
+            players[0],
+            players[1],
+            status
+        ));
+    }
+
+    let mut output = format!("**{}** ({})\n```\n", name, bracket.format.name());
+    for line in lines {
+        //Leaves room for the ellipsis and the end of the code block.
+        if output.encode_utf16().count() + line.encode_utf16().count()
+            > DISCORD_CHARACTER_LIMIT - 10
+        {
+            output.push_str("...\n");
+            break;
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output.push_str("```");
+
+    output
+}
+
+async fn database(ctx: &serenity::all::Context) -> Result<std::sync::Arc<Pool<Postgres>>> {
+    let data = ctx.data.read().await;
+    Ok(data
+        .get::<crate::DatabaseKey>()
+        .cloned()
+        .context("Failed to connect to database.")?
+        .db)
+}
+
+async fn linked_player(db: &Pool<Postgres>, discord_id: u64) -> Result<(i64, String)> {
+    sqlx::query_as("SELECT id, name FROM players WHERE discord_id = $1")
+        .bind(discord_id as i64)
+        .fetch_optional(db)
+        .await?
+        .context("Link your bonk.io account with \"/elo link\" first.")
+}
+
+///Tournament id and whether it has started.
+async fn signup_state(db: &Pool<Postgres>, name: &str) -> Result<(i64, bool)> {
+    sqlx::query_as("SELECT id, bracket IS NOT NULL FROM tournaments WHERE name = $1")
+        .bind(name)
+        .fetch_optional(db)
+        .await?
+        .with_context(|| format!("Unable to find tournament {}.", name))
+}
+
+///Signed up players from the highest display rating on the tournament's leaderboard.
+///Players without a rating go last.
+async fn seeded_players<'c>(
+    db: impl sqlx::PgExecutor<'c>,
+    tournament_id: i64,
+) -> Result<Vec<(i64, String)>> {
+    Ok(sqlx::query_as(
+        "SELECT p.id, p.name FROM tournament_players t \
+        JOIN tournaments tr ON tr.id = t.tournament_id \
+        JOIN players p ON p.id = t.player_id \
+        LEFT JOIN lb_players l ON l.player_id = p.id AND l.lb_id = tr.lb_id \
+        WHERE t.tournament_id = $1 ORDER BY l.display_rating DESC NULLS LAST, p.id",
+    )
+    .bind(tournament_id)
+    .fetch_all(db)
+    .await?)
+}
+
+///Every bonk name of a seeded player.
+async fn player_names(db: &Pool<Postgres>, tournament_id: i64, seed: usize) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT n.name FROM tournament_players t \
+        JOIN player_names n ON n.player_id = t.player_id \
+        WHERE t.tournament_id = $1 AND t.seed = $2",
+    )
+    .bind(tournament_id)
+    .bind(seed as i32)
+    .fetch_all(db)
+    .await?)
+}
+
+async fn save_bracket(
+    trans: &mut Transaction<'static, Postgres>,
+    tournament_id: i64,
+    bracket: &Bracket,
+) -> Result<()> {
+    sqlx::query("UPDATE tournaments SET bracket = $1 WHERE id = $2")
+        .bind(serde_json::to_value(bracket)?)
+        .bind(tournament_id)
+        .execute(&mut **trans)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,416 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    SingleElimination,
+    DoubleElimination,
+    Swiss,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Format> {
+        match format {
+            "single" | "se" => Some(Format::SingleElimination),
+            "double" | "de" => Some(Format::DoubleElimination),
+            "swiss" => Some(Format::Swiss),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::SingleElimination => "Single elimination",
+            Format::DoubleElimination => "Double elimination",
+            Format::Swiss => "Swiss",
+        }
+    }
+}
+
+///Where a player in a match comes from.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum Slot {
+    Player(usize),
+    Bye,
+    Winner(usize),
+    Loser(usize),
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum Stage {
+    Winners,
+    Losers,
+    GrandFinal,
+    Swiss,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct BracketMatch {
+    pub stage: Stage,
+    pub round: usize,
+    pub slots: [Slot; 2],
+    ///Indices into Bracket::players once the slots are known. None is a bye.
+    pub players: [Option<usize>; 2],
+    pub done: bool,
+    pub winner: Option<usize>,
+    ///Whether a room was opened for the match.
+    pub opened: bool,
+}
+
+impl BracketMatch {
+    pub fn loser(&self) -> Option<usize> {
+        if !self.done {
+            return None;
+        }
+        self.players
+            .iter()
+            .flatten()
+            .copied()
+            .find(|player| Some(*player) != self.winner)
+    }
+
+    ///Whether both players are known and the match hasn't been played.
+    pub fn ready(&self) -> bool {
+        !self.done && self.players.iter().all(|player| player.is_some())
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Bracket {
+    pub format: Format,
+    ///Player names in seed order.
+    pub players: Vec<String>,
+    pub matches: Vec<BracketMatch>,
+    ///Number of Swiss rounds.
+    pub rounds: usize,
+}
+
+impl Bracket {
+    ///Builds a bracket from players in seed order. Swiss rounds default to enough rounds
+    ///for one undefeated player.
+    pub fn new(format: Format, players: Vec<String>, rounds: Option<usize>) -> Bracket {
+        let mut bracket = Bracket {
+            format,
+            players,
+            matches: vec![],
+            rounds: 0,
+        };
+
+        if let Format::Swiss = format {
+            bracket.rounds = rounds
+                .unwrap_or(bracket.players.len().next_power_of_two().trailing_zeros() as usize)
+                .max(1);
+            bracket.pair_swiss_round();
+        } else {
+            bracket.build_elimination();
+        }
+        bracket.resolve();
+
+        bracket
+    }
+
+    ///Records the winner of a match and moves players into the matches that follow it.
+    pub fn report(&mut self, match_idx: usize, winner: usize) -> Result<()> {
+        let bracket_match = self
+            .matches
+            .get_mut(match_idx)
+            .context("That match doesn't exist.")?;
+        if bracket_match.done {
+            return Err(anyhow!("That match is already over."));
+        }
+        if !bracket_match.ready() {
+            return Err(anyhow!("The players of that match aren't known yet."));
+        }
+        if !bracket_match.players.contains(&Some(winner)) {
+            return Err(anyhow!("That player isn't in the match."));
+        }
+
+        bracket_match.done = true;
+        bracket_match.winner = Some(winner);
+        self.resolve();
+
+        Ok(())
+    }
+
+    ///Matches that are ready to be played and don't have a room yet.
+    pub fn unopened_matches(&self) -> Vec<usize> {
+        self.matches
+            .iter()
+            .enumerate()
+            .filter(|(_, bracket_match)| bracket_match.ready() && !bracket_match.opened)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.matches.iter().all(|bracket_match| bracket_match.done)
+    }
+
+    ///Player indices from first to last. Elimination brackets only rank the winner.
+    pub fn standings(&self) -> Vec<usize> {
+        if let Format::Swiss = self.format {
+            let wins = self.wins();
+            let mut standings = (0..self.players.len()).collect::<Vec<usize>>();
+            standings.sort_by_key(|player| std::cmp::Reverse(wins.get(*player).copied()));
+            standings
+        } else {
+            self.matches
+                .last()
+                .and_then(|bracket_match| bracket_match.winner)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    pub fn player_name(&self, player: Option<usize>) -> String {
+        match player.and_then(|player| self.players.get(player)) {
+            Some(name) => name.clone(),
+            None => "bye".to_string(),
+        }
+    }
+
+    fn build_elimination(&mut self) {
+        let size = self.players.len().next_power_of_two().max(2);
+        let seed_slot = |seed: usize| {
+            if seed < self.players.len() {
+                Slot::Player(seed)
+            } else {
+                Slot::Bye
+            }
+        };
+        let first_round = seed_order(size)
+            .chunks(2)
+            .map(|seeds| [seed_slot(seeds[0]), seed_slot(seeds[1])])
+            .collect::<Vec<[Slot; 2]>>();
+        let mut winners = vec![first_round
+            .into_iter()
+            .map(|slots| self.add_match(Stage::Winners, 0, slots))
+            .collect::<Vec<usize>>()];
+
+        while let Some(round) = winners.last().filter(|round| round.len() > 1) {
+            let round = round.clone();
+            let round_num = winners.len();
+            winners.push(
+                round
+                    .chunks(2)
+                    .map(|pair| {
+                        self.add_match(
+                            Stage::Winners,
+                            round_num,
+                            [Slot::Winner(pair[0]), Slot::Winner(pair[1])],
+                        )
+                    })
+                    .collect(),
+            );
+        }
+
+        if self.format != Format::DoubleElimination || winners.len() < 2 {
+            return;
+        }
+
+        //Losers of each winners round drop in against the survivors of the losers bracket.
+        //They drop in reversed to put off rematches.
+        let mut round_num = 0;
+        let mut losers = winners[0]
+            .chunks(2)
+            .map(|pair| {
+                self.add_match(
+                    Stage::Losers,
+                    round_num,
+                    [Slot::Loser(pair[0]), Slot::Loser(pair[1])],
+                )
+            })
+            .collect::<Vec<usize>>();
+        for winners_round in winners.iter().skip(1) {
+            round_num += 1;
+            losers = losers
+                .iter()
+                .zip(winners_round.iter().rev())
+                .map(|(survivor, loser)| {
+                    self.add_match(
+                        Stage::Losers,
+                        round_num,
+                        [Slot::Winner(*survivor), Slot::Loser(*loser)],
+                    )
+                })
+                .collect();
+
+            if losers.len() > 1 {
+                round_num += 1;
+                losers = losers
+                    .chunks(2)
+                    .map(|pair| {
+                        self.add_match(
+                            Stage::Losers,
+                            round_num,
+                            [Slot::Winner(pair[0]), Slot::Winner(pair[1])],
+                        )
+                    })
+                    .collect();
+            }
+        }
+
+        let (Some(winners_final), Some(losers_final)) = (
+            winners.last().and_then(|round| round.first()),
+            losers.first(),
+        ) else {
+            return;
+        };
+        self.add_match(
+            Stage::GrandFinal,
+            0,
+            [Slot::Winner(*winners_final), Slot::Winner(*losers_final)],
+        );
+    }
+
+    ///Pairs players with the same number of wins who haven't played each other. The lowest
+    ///ranked player without a bye gets one when the player count is odd.
+    fn pair_swiss_round(&mut self) {
+        let round = self
+            .matches
+            .iter()
+            .map(|bracket_match| bracket_match.round + 1)
+            .max()
+            .unwrap_or(0);
+        let mut order = self.standings();
+
+        if order.len() % 2 == 1 {
+            let bye = order
+                .iter()
+                .rposition(|player| !self.had_bye(*player))
+                .unwrap_or(order.len() - 1);
+            let player = order.remove(bye);
+            self.add_match(Stage::Swiss, round, [Slot::Player(player), Slot::Bye]);
+        }
+
+        while !order.is_empty() {
+            let first = order.remove(0);
+            let opponent = order
+                .iter()
+                .position(|player| !self.played(first, *player))
+                .unwrap_or(0);
+            if opponent >= order.len() {
+                break;
+            }
+            let second = order.remove(opponent);
+            self.add_match(
+                Stage::Swiss,
+                round,
+                [Slot::Player(first), Slot::Player(second)],
+            );
+        }
+    }
+
+    ///Fills in players from finished matches and ends matches that have a bye.
+    fn resolve(&mut self) {
+        loop {
+            let resolved = self
+                .matches
+                .iter()
+                .map(|bracket_match| bracket_match.slots.map(|slot| self.slot_player(slot)))
+                .collect::<Vec<[Option<Option<usize>>; 2]>>();
+
+            let mut changed = false;
+            for (bracket_match, players) in self.matches.iter_mut().zip(resolved) {
+                if bracket_match.done {
+                    continue;
+                }
+                for (player, resolved) in bracket_match.players.iter_mut().zip(players) {
+                    if let Some(resolved) = resolved {
+                        *player = resolved;
+                    }
+                }
+                if let [Some(first), Some(second)] = players {
+                    if first.is_none() || second.is_none() {
+                        bracket_match.done = true;
+                        bracket_match.winner = first.or(second);
+                        changed = true;
+                    }
+                }
+            }
+
+            if changed {
+                continue;
+            }
+
+            let rounds_played = self
+                .matches
+                .iter()
+                .map(|bracket_match| bracket_match.round + 1)
+                .max()
+                .unwrap_or(0);
+            if self.format == Format::Swiss && self.finished() && rounds_played < self.rounds {
+                self.pair_swiss_round();
+                continue;
+            }
+
+            break;
+        }
+    }
+
+    ///The player in a slot. None while the slot isn't known yet and Some(None) for a bye.
+    fn slot_player(&self, slot: Slot) -> Option<Option<usize>> {
+        match slot {
+            Slot::Player(player) => Some(Some(player)),
+            Slot::Bye => Some(None),
+            Slot::Winner(idx) => self
+                .matches
+                .get(idx)
+                .filter(|bracket_match| bracket_match.done)
+                .map(|bracket_match| bracket_match.winner),
+            Slot::Loser(idx) => self
+                .matches
+                .get(idx)
+                .filter(|bracket_match| bracket_match.done)
+                .map(|bracket_match| bracket_match.loser()),
+        }
+    }
+
+    fn add_match(&mut self, stage: Stage, round: usize, slots: [Slot; 2]) -> usize {
+        self.matches.push(BracketMatch {
+            stage,
+            round,
+            slots,
+            players: [None, None],
+            done: false,
+            winner: None,
+            opened: false,
+        });
+        self.matches.len() - 1
+    }
+
+    pub fn wins(&self) -> Vec<usize> {
+        let mut wins = vec![0; self.players.len()];
+        for winner in self.matches.iter().filter_map(|m| m.winner) {
+            if let Some(wins) = wins.get_mut(winner) {
+                *wins += 1;
+            }
+        }
+        wins
+    }
+
+    fn had_bye(&self, player: usize) -> bool {
+        self.matches
+            .iter()
+            .any(|m| m.slots.contains(&Slot::Player(player)) && m.slots.contains(&Slot::Bye))
+    }
+
+    fn played(&self, player: usize, other: usize) -> bool {
+        self.matches.iter().any(|m| {
+            m.slots.contains(&Slot::Player(player)) && m.slots.contains(&Slot::Player(other))
+        })
+    }
+}
+
+///Seeds in bracket order so the top seeds meet as late as possible, e.g. 0, 3, 1, 2.
+fn seed_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let len = order.len() * 2;
+        order = order
+            .iter()
+            .flat_map(|seed| [*seed, len - 1 - seed])
+            .collect();
+    }
+    order
+}